@@ -10,7 +10,8 @@ benchmarkMetrics = [
     "t_opt", "t_runner", "t_extractor", "t_compiler",
     "mig_size", "mig_opt_size", "mig_pis", "mig_pos",
     "instruction_count",
-    "egraph_classes", "egraph_nodes", "egraph_size"
+    "egraph_classes", "egraph_nodes", "egraph_size",
+    "latency", "energy"
 ]
 groupingMetric = "instruction_count"
 groups = [30, 100]
@@ -74,7 +75,8 @@ for metric in benchmarkMetrics:
     for bench in benchmarks:
         m = 0
         for row in rows:
-            m = max(row["results"][bench][metric], m)
+            # rows of older versions may lack some metrics
+            m = max(row["results"][bench].get(metric, 0), m)
         metricValues[bench] = m
 
 # Determine the group for each benchmark
@@ -120,7 +122,7 @@ def visualize(rows, file_prefix, benchmarks):
             values = []
             for bench in benchmarks:
                 if bench in results:
-                    values.append(results[bench][col])
+                    values.append(results[bench].get(col, 0))
                 else:
                     values.append(0)
             ax.bar(x + width * multiplier, values, width, label=row["description"], align="edge")
@@ -138,6 +140,6 @@ for i, groupedRows in enumerate(grouped):
 for row in rows[1:]:
     for benchmark, metrics in row["results"].items():
         for metric, value in metrics.items():
-            baseline = rows[0]["results"][benchmark][metric]
+            baseline = rows[0]["results"][benchmark].get(metric, 0)
             metrics[metric] = 0 if value == 0 or baseline == 0 else (baseline - value) / baseline
 visualize(rows[1:], "rel_diff", benchmarks)
@@ -1,4 +1,6 @@
 #!/bin/sh
+# usage: bench_ambit.sh [architecture preset or file]...
+# Every given architecture (default: ambit) results in a separate row in the output file.
 
 OUT_FILE="ambit.csv"
 
@@ -18,23 +20,37 @@ cmake -DCMAKE_BUILD_TYPE=Release ../..
 make lime_ambit_benchmark || exit
 cd ..
 
-printf "%s\t%s\t%s" "$time" "$rev" "$description" >> "$OUT_FILE"
+if [ $# -eq 0 ]; then
+  set -- ambit
+fi
 
-for benchmark in \
-  'mux' 'fa' 'add2' 'add3' 'add4' 'add32' 'add64' 'gt' 'mul2' 'mul6' 'pop2' 'pop4' 'kogge_stone' \
-  'ntk/ctrl.aig' 'ntk/dec.aig' 'ntk/int2float.aig' 'ntk/router.aig'
+for architecture in "$@"
 do
-  benchmarkName=$(basename "$benchmark" | cut -d. -f1)
-  echo "Running $benchmarkName..."
-  out=$(./build/lime_ambit_benchmark "$benchmark")
-  if [ $? -eq 0 ]; then
-    printf "\t\t%s\t%s" "$benchmarkName" "$out" >> "$OUT_FILE"
-  else
-    echo "failed (output: $out)"
-  fi
+  echo "Architecture $architecture"
+  # relative architecture files are given relative to the repository root
+  case "$architecture" in
+    /*) architecture_arg="$architecture" ;;
+    *.*) architecture_arg="../$architecture" ;;
+    *) architecture_arg="$architecture" ;;
+  esac
+  printf "%s\t%s\t%s (%s)" "$time" "$rev" "$description" "$architecture" >> "$OUT_FILE"
+
+  for benchmark in \
+    'mux' 'fa' 'add2' 'add3' 'add4' 'add32' 'add64' 'gt' 'mul2' 'mul6' 'pop2' 'pop4' 'kogge_stone' \
+    'ntk/ctrl.aig' 'ntk/dec.aig' 'ntk/int2float.aig' 'ntk/router.aig'
+  do
+    benchmarkName=$(basename "$benchmark" | cut -d. -f1)
+    echo "Running $benchmarkName..."
+    out=$(./build/lime_ambit_benchmark "$benchmark" "$architecture_arg")
+    if [ $? -eq 0 ]; then
+      printf "\t\t%s\t%s" "$benchmarkName" "$out" >> "$OUT_FILE"
+    else
+      echo "failed (output: $out)"
+    fi
+  done
+
+  echo >> "$OUT_FILE"
 done
 
-echo >> "$OUT_FILE"
-
 echo "Generating visualizations..."
 python3 visualize.py
@@ -0,0 +1,403 @@
+use super::{Address, Architecture, BitwiseAddress, BitwiseOperand, Instruction, Program};
+use rustc_hash::FxHashSet;
+use serde::{Deserialize, Serialize};
+use std::cmp::max;
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+
+/// Describes the rows of a DRAM subarray. The row addresses `0..b_group_rows` are reserved for the
+/// B-group, whose decoder maps each of them to one or more of the wordlines of the T and DCC rows,
+/// see [b_group_address]. They are followed by the C-group, whose first two rows hold the
+/// constants 0 and 1. All remaining rows form the D-group, which holds the inputs, outputs and
+/// spilled values.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Subarray {
+    /// total number of rows
+    pub rows: u32,
+    pub b_group_rows: u32,
+    pub c_group_rows: u32,
+    /// the row of the first input, the other inputs follow consecutively (defaults to the first
+    /// D-group row)
+    pub first_input_row: Option<u32>,
+    /// the row of the first output, the other outputs follow consecutively (defaults to the row
+    /// after the last input)
+    pub first_output_row: Option<u32>,
+}
+
+/// An address of a physically addressed program. D-group and C-group rows are referred to by their
+/// row number, B-group addresses are decoded by the B-group decoder and thus kept as they are.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PhysicalAddress {
+    Row(u32),
+    Bitwise(BitwiseAddress),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PhysicalInstruction {
+    AAP(PhysicalAddress, PhysicalAddress),
+    AP(PhysicalAddress),
+}
+
+#[derive(Debug, Clone)]
+pub struct PhysicalProgram<'a> {
+    pub architecture: &'a Architecture,
+    pub subarray: Subarray,
+    pub instructions: Vec<PhysicalInstruction>,
+    /// the row of each input
+    pub inputs: Vec<u32>,
+    /// the row of each output
+    pub outputs: Vec<u32>,
+    /// the number of distinct rows used for spilling
+    pub spill_rows: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AllocationError {
+    /// The B-group has fewer row addresses than the architecture has bitwise addresses.
+    BGroupTooSmall { required: u32 },
+    /// The C-group cannot hold both constant rows.
+    CGroupTooSmall,
+    /// The input or output rows do not lie within the D-group or overlap.
+    InvalidPlacement,
+    /// All D-group rows are in use when the instruction needs another spill row.
+    OutOfRows { instruction: usize },
+    /// The bitwise operand is not part of the architecture, so it has no B-group address.
+    UnknownOperand(BitwiseOperand),
+}
+
+impl Default for Subarray {
+    /// A subarray of 1024 rows with the 16 B-group addresses and two C-group rows of Ambit.
+    fn default() -> Self {
+        Self {
+            rows: 1024,
+            b_group_rows: 16,
+            c_group_rows: 2,
+            first_input_row: None,
+            first_output_row: None,
+        }
+    }
+}
+
+impl<'a> Program<'a> {
+    /// Assigns a physical row of the given subarray to each input, output and spill row. A spill
+    /// row is released after the last instruction accessing it, so that later spills can reuse it.
+    pub fn allocate_rows(
+        &self,
+        subarray: &Subarray,
+    ) -> Result<PhysicalProgram<'a>, AllocationError> {
+        let required = b_group_addresses(self.architecture);
+        if subarray.b_group_rows < required {
+            return Err(AllocationError::BGroupTooSmall { required });
+        }
+        if subarray.c_group_rows < 2 {
+            return Err(AllocationError::CGroupTooSmall);
+        }
+        let c_group = subarray.b_group_rows;
+        let d_group = c_group + subarray.c_group_rows..subarray.rows;
+
+        let (num_inputs, num_outputs, num_spills) = self.symbolic_rows();
+        let first_input = subarray.first_input_row.unwrap_or(d_group.start);
+        let first_output = subarray
+            .first_output_row
+            .unwrap_or(first_input.saturating_add(num_inputs));
+        let inputs = first_input..first_input.saturating_add(num_inputs);
+        let outputs = first_output..first_output.saturating_add(num_outputs);
+        let within_d_group = |rows: &Range<u32>| {
+            rows.is_empty() || d_group.start <= rows.start && rows.end <= d_group.end
+        };
+        let overlapping = inputs.start < outputs.end && outputs.start < inputs.end;
+        if overlapping || !within_d_group(&inputs) || !within_d_group(&outputs) {
+            return Err(AllocationError::InvalidPlacement);
+        }
+
+        // the index of the last instruction accessing each spill row
+        let mut last_access = vec![0; num_spills];
+        for (idx, instruction) in self.instructions.iter().enumerate() {
+            for spill in spills(instruction) {
+                last_access[spill as usize] = idx;
+            }
+        }
+
+        // rows are popped from the end, so the lowest free row is used first
+        let mut free_rows: Vec<u32> = d_group
+            .rev()
+            .filter(|row| !inputs.contains(row) && !outputs.contains(row))
+            .collect();
+        let mut spill_rows = vec![None; num_spills];
+        let mut used_spill_rows = FxHashSet::default();
+        let mut instructions = Vec::with_capacity(self.instructions.len());
+        for (idx, instruction) in self.instructions.iter().enumerate() {
+            for spill in spills(instruction) {
+                if spill_rows[spill as usize].is_none() {
+                    let row = free_rows
+                        .pop()
+                        .ok_or(AllocationError::OutOfRows { instruction: idx })?;
+                    used_spill_rows.insert(row);
+                    spill_rows[spill as usize] = Some(row);
+                }
+            }
+            let physical = |address: Address| {
+                Ok(match address {
+                    Address::In(i) => PhysicalAddress::Row(inputs.start + i as u32),
+                    Address::Out(i) => PhysicalAddress::Row(outputs.start + i as u32),
+                    Address::Spill(i) => PhysicalAddress::Row(spill_rows[i as usize].unwrap()),
+                    Address::Const(value) => PhysicalAddress::Row(c_group + value as u32),
+                    Address::Bitwise(address) => {
+                        // rejects operands that the B-group decoder cannot address
+                        b_group_address(self.architecture, address)?;
+                        PhysicalAddress::Bitwise(address)
+                    }
+                })
+            };
+            instructions.push(match *instruction {
+                Instruction::AAP(from, to) => {
+                    PhysicalInstruction::AAP(physical(from)?, physical(to)?)
+                }
+                Instruction::AP(address) => PhysicalInstruction::AP(physical(address)?),
+            });
+            for spill in spills(instruction) {
+                if last_access[spill as usize] == idx {
+                    if let Some(row) = spill_rows[spill as usize].take() {
+                        free_rows.push(row);
+                    }
+                }
+            }
+        }
+
+        Ok(PhysicalProgram {
+            architecture: self.architecture,
+            subarray: *subarray,
+            instructions,
+            inputs: inputs.collect(),
+            outputs: outputs.collect(),
+            spill_rows: used_spill_rows.len() as u32,
+        })
+    }
+
+    /// Returns the number of input, output and spill rows referenced by this program, i.e. the
+    /// highest index of each plus one.
+    fn symbolic_rows(&self) -> (u32, u32, usize) {
+        let (mut inputs, mut outputs, mut spills) = (0, 0, 0);
+        for address in self.instructions.iter().flat_map(addresses) {
+            match address {
+                Address::In(i) => inputs = max(inputs, i as u32 + 1),
+                Address::Out(i) => outputs = max(outputs, i as u32 + 1),
+                Address::Spill(i) => spills = max(spills, i as usize + 1),
+                _ => {}
+            }
+        }
+        (inputs, outputs, spills)
+    }
+}
+
+/// Returns the number of B-group row addresses required by the given architecture, i.e. one for
+/// each bitwise operand and each multi-row activation.
+pub fn b_group_addresses(architecture: &Architecture) -> u32 {
+    (t_rows(architecture).len()
+        + 2 * architecture.num_dcc as usize
+        + architecture.multi_activations.len()) as u32
+}
+
+/// Returns the B-group row address of the given bitwise address. As in Ambit, the T rows come first,
+/// followed by the regular and negated wordline of each DCC row and the multi-row activations.
+pub fn b_group_address(
+    architecture: &Architecture,
+    address: BitwiseAddress,
+) -> Result<u32, AllocationError> {
+    let t_rows = t_rows(architecture);
+    let address = match address {
+        BitwiseAddress::Single(operand @ BitwiseOperand::T(t)) => t_rows
+            .iter()
+            .position(|row| *row == t)
+            .ok_or(AllocationError::UnknownOperand(operand))?,
+        BitwiseAddress::Single(operand @ BitwiseOperand::DCC { inverted, index }) => {
+            if index >= architecture.num_dcc {
+                return Err(AllocationError::UnknownOperand(operand));
+            }
+            t_rows.len() + 2 * index as usize + inverted as usize
+        }
+        BitwiseAddress::Multiple(idx) => t_rows.len() + 2 * architecture.num_dcc as usize + idx,
+    };
+    Ok(address as u32)
+}
+
+/// Returns the indices of the T rows of the given architecture in ascending order.
+fn t_rows(architecture: &Architecture) -> Vec<u8> {
+    let mut t_rows: Vec<u8> = architecture
+        .multi_activations
+        .iter()
+        .flatten()
+        .filter_map(|operand| match operand {
+            BitwiseOperand::T(t) => Some(*t),
+            _ => None,
+        })
+        .collect();
+    t_rows.sort_unstable();
+    t_rows.dedup();
+    t_rows
+}
+
+fn addresses(instruction: &Instruction) -> impl Iterator<Item = Address> {
+    match *instruction {
+        Instruction::AAP(from, to) => [Some(from), Some(to)],
+        Instruction::AP(address) => [Some(address), None],
+    }
+    .into_iter()
+    .flatten()
+}
+
+fn spills(instruction: &Instruction) -> impl Iterator<Item = u32> {
+    addresses(instruction).filter_map(|address| match address {
+        Address::Spill(i) => Some(i),
+        _ => None,
+    })
+}
+
+impl Display for PhysicalProgram<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let write_address = |f: &mut Formatter<'_>, a: &PhysicalAddress| -> std::fmt::Result {
+            match a {
+                PhysicalAddress::Row(row) => write!(f, "R{row}"),
+                PhysicalAddress::Bitwise(BitwiseAddress::Single(operand)) => {
+                    write!(f, "{operand}")
+                }
+                PhysicalAddress::Bitwise(BitwiseAddress::Multiple(id)) => {
+                    write!(f, "[")?;
+                    for (i, operand) in self.architecture.multi_activations[*id].iter().enumerate()
+                    {
+                        if i != 0 {
+                            write!(f, ", ")?;
+                        }
+                        write!(f, "{operand}")?;
+                    }
+                    write!(f, "]")
+                }
+            }
+        };
+
+        for instruction in &self.instructions {
+            match instruction {
+                PhysicalInstruction::AAP(a, b) => {
+                    write!(f, "AAP ")?;
+                    write_address(f, a)?;
+                    write!(f, " ")?;
+                    write_address(f, b)?;
+                    writeln!(f)?;
+                }
+                PhysicalInstruction::AP(a) => {
+                    write!(f, "AP ")?;
+                    write_address(f, a)?;
+                    writeln!(f)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for AllocationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BGroupTooSmall { required } => write!(
+                f,
+                "the architecture requires {required} B-group row addresses"
+            ),
+            Self::CGroupTooSmall => write!(f, "the C-group has to hold both constant rows"),
+            Self::InvalidPlacement => write!(
+                f,
+                "the input and output rows have to lie within the D-group without overlapping"
+            ),
+            Self::OutOfRows { instruction } => write!(
+                f,
+                "the subarray has no free row left to spill to at instruction {instruction}"
+            ),
+            Self::UnknownOperand(operand) => {
+                write!(f, "{operand} is not part of the architecture")
+            }
+        }
+    }
+}
+
+impl std::error::Error for AllocationError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambit::presets::AMBIT;
+
+    /// A subarray of AMBIT whose D-group has the given number of rows.
+    fn subarray(d_group_rows: u32) -> Subarray {
+        let b_group_rows = b_group_addresses(&AMBIT);
+        Subarray {
+            rows: b_group_rows + 2 + d_group_rows,
+            b_group_rows,
+            ..Subarray::default()
+        }
+    }
+
+    #[test]
+    fn out_of_rows() {
+        // both spills are live at the same time
+        let program = Program::new(
+            &AMBIT,
+            vec![
+                Instruction::AAP(Address::In(0), Address::Spill(0)),
+                Instruction::AAP(Address::In(0), Address::Spill(1)),
+                Instruction::AAP(Address::Spill(0), BitwiseOperand::T(0).into()),
+                Instruction::AAP(Address::Spill(1), Address::Out(0)),
+            ],
+        );
+        assert_eq!(
+            program.allocate_rows(&subarray(3)).unwrap_err(),
+            AllocationError::OutOfRows { instruction: 1 }
+        );
+        let physical = program.allocate_rows(&subarray(4)).unwrap();
+        assert_eq!(physical.spill_rows, 2);
+    }
+
+    #[test]
+    fn spill_row_reused_after_last_access() {
+        let program = Program::new(
+            &AMBIT,
+            vec![
+                Instruction::AAP(Address::In(0), Address::Spill(0)),
+                Instruction::AAP(Address::Spill(0), BitwiseOperand::T(0).into()),
+                Instruction::AAP(Address::In(0), Address::Spill(1)),
+                Instruction::AAP(Address::Spill(1), Address::Out(0)),
+            ],
+        );
+        let physical = program.allocate_rows(&subarray(3)).unwrap();
+        assert_eq!(physical.spill_rows, 1);
+        let PhysicalInstruction::AAP(_, first) = physical.instructions[0] else {
+            panic!("expected an AAP");
+        };
+        let PhysicalInstruction::AAP(_, second) = physical.instructions[2] else {
+            panic!("expected an AAP");
+        };
+        assert_eq!(first, second);
+        assert_eq!(physical.inputs, vec![subarray(0).rows]);
+        assert_eq!(physical.outputs, vec![subarray(0).rows + 1]);
+    }
+
+    #[test]
+    fn unknown_operand() {
+        let unknown = BitwiseOperand::T(7);
+        let program = Program::new(
+            &AMBIT,
+            vec![Instruction::AAP(Address::In(0), unknown.into())],
+        );
+        assert_eq!(
+            program.allocate_rows(&subarray(2)).unwrap_err(),
+            AllocationError::UnknownOperand(unknown)
+        );
+        let dcc = BitwiseOperand::DCC {
+            inverted: true,
+            index: AMBIT.num_dcc,
+        };
+        assert_eq!(
+            b_group_address(&AMBIT, BitwiseAddress::Single(dcc)),
+            Err(AllocationError::UnknownOperand(dcc))
+        );
+    }
+}
@@ -0,0 +1,359 @@
+use super::verification::{patterns, simulate_classes, MAX_EXHAUSTIVE_INPUTS};
+use eggmock::egg::{Analysis, AstSize, DidMerge, EGraph, Extractor, Id};
+use eggmock::MigLanguage;
+use rustc_hash::FxHashMap;
+use smallvec::{smallvec, SmallVec};
+
+/// Number of 64-bit lanes of the signatures and truth tables of e-classes.
+const LANES: usize = 4;
+/// Classes depending on at most this many inputs have an exact truth table, which has one bit for
+/// each of the `2^8 = 64 * LANES` patterns of their support.
+const MAX_TABLE_SUPPORT: usize = 8;
+
+type Bits = [u64; LANES];
+/// Inputs in ascending order.
+type Support = SmallVec<[u64; MAX_TABLE_SUPPORT]>;
+
+/// An e-class analysis that knows which e-classes evaluate to a constant.
+pub trait ConstantAnalysis: Analysis<MigLanguage> {
+    /// Returns the value of the class with the given data if it is known to be constant.
+    fn constant(data: &Self::Data) -> Option<bool>;
+}
+
+/// Attaches the value of each e-class to it if it evaluates to a constant, e.g. `M(a, 0, !1)`, and
+/// merges such classes with the class of the constant, so that the compiler loads them from the
+/// constant rows instead of computing them.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct ConstantFolding;
+
+/// Attaches the function computed by each e-class to it, see [ClassFunction]. Constants are folded
+/// like with [ConstantFolding].
+#[derive(Debug, Copy, Clone, Default)]
+pub struct FunctionalAnalysis;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassFunction {
+    /// the value of the class if it is constant
+    pub constant: Option<bool>,
+    /// the values of the class on random input patterns, which are the same for all e-graphs
+    pub signature: Bits,
+    /// a superset of the inputs the class depends on, if it has at most [MAX_EXHAUSTIVE_INPUTS]
+    /// inputs
+    pub support: Option<Support>,
+    /// the truth table of the class if it depends on at most [MAX_TABLE_SUPPORT] inputs, in which
+    /// case the support contains exactly these inputs. Bit `m` is the value of the class when
+    /// assigning bit `i` of `m` to the `i`-th input of the support.
+    pub table: Option<Bits>,
+}
+
+impl ConstantFolding {
+    /// Returns the value of the given node if it is constant, where `constant` returns the value
+    /// of a child class if it is constant.
+    fn fold(enode: &MigLanguage, constant: impl Fn(Id) -> Option<bool>) -> Option<bool> {
+        match enode {
+            MigLanguage::False => Some(false),
+            MigLanguage::Input(_) => None,
+            MigLanguage::Not(id) => constant(*id).map(|value| !value),
+            // the majority is constant as soon as two operands are the same constant
+            MigLanguage::Maj(children) => match children.map(&constant) {
+                [Some(a), Some(b), _] if a == b => Some(a),
+                [Some(a), _, Some(c)] if a == c => Some(a),
+                [_, Some(b), Some(c)] if b == c => Some(b),
+                _ => None,
+            },
+        }
+    }
+
+    /// Merges the given class with the class of its value if it is constant.
+    fn merge_constant<A: Analysis<MigLanguage>>(
+        egraph: &mut EGraph<MigLanguage, A>,
+        id: Id,
+        value: Option<bool>,
+    ) {
+        if let Some(value) = value {
+            let mut constant = egraph.add(MigLanguage::False);
+            if value {
+                constant = egraph.add(MigLanguage::Not(constant));
+            }
+            egraph.union(id, constant);
+        }
+    }
+}
+
+impl Analysis<MigLanguage> for ConstantFolding {
+    type Data = Option<bool>;
+
+    fn make(egraph: &mut EGraph<MigLanguage, Self>, enode: &MigLanguage) -> Self::Data {
+        Self::fold(enode, |id| egraph[id].data)
+    }
+
+    fn merge(&mut self, a: &mut Self::Data, b: Self::Data) -> DidMerge {
+        let merged = a.or(b);
+        let did_merge = DidMerge(merged != *a, merged != b);
+        *a = merged;
+        did_merge
+    }
+
+    fn modify(egraph: &mut EGraph<MigLanguage, Self>, id: Id) {
+        let value = egraph[id].data;
+        Self::merge_constant(egraph, id, value);
+    }
+}
+
+impl ConstantAnalysis for ConstantFolding {
+    fn constant(data: &Self::Data) -> Option<bool> {
+        *data
+    }
+}
+
+impl ConstantAnalysis for () {
+    fn constant(_: &Self::Data) -> Option<bool> {
+        None
+    }
+}
+
+impl ConstantAnalysis for FunctionalAnalysis {
+    fn constant(data: &Self::Data) -> Option<bool> {
+        data.constant
+    }
+}
+
+impl Analysis<MigLanguage> for FunctionalAnalysis {
+    type Data = ClassFunction;
+
+    fn make(egraph: &mut EGraph<MigLanguage, Self>, enode: &MigLanguage) -> Self::Data {
+        let constant = ConstantFolding::fold(enode, |id| egraph[id].data.constant);
+        let mut function = match enode {
+            MigLanguage::False => ClassFunction {
+                constant: None,
+                signature: [0; LANES],
+                support: Some(Support::new()),
+                table: Some([0; LANES]),
+            },
+            MigLanguage::Input(i) => ClassFunction {
+                constant: None,
+                signature: random_bits(*i),
+                support: Some(smallvec![*i]),
+                table: Some([0xAAAA_AAAA_AAAA_AAAA; LANES]),
+            },
+            MigLanguage::Not(id) => {
+                let function = &egraph[*id].data;
+                ClassFunction {
+                    constant: None,
+                    signature: function.signature.map(|bits| !bits),
+                    support: function.support.clone(),
+                    table: function.table.map(|table| table.map(|bits| !bits)),
+                }
+            }
+            MigLanguage::Maj(children) => {
+                let [a, b, c] = children.map(|id| &egraph[id].data);
+                maj(a, b, c)
+            }
+        };
+        // the truth table also reveals constants the operands do not
+        function.constant = constant.or(function.constant);
+        function
+    }
+
+    fn merge(&mut self, a: &mut Self::Data, b: Self::Data) -> DidMerge {
+        // both describe the same function, so the smaller support is the more precise one
+        let len =
+            |function: &ClassFunction| function.support.as_ref().map_or(usize::MAX, |s| s.len());
+        let mut merged = if len(&b) < len(a) {
+            b.clone()
+        } else {
+            a.clone()
+        };
+        merged.constant = a.constant.or(b.constant);
+        let did_merge = DidMerge(merged != *a, merged != b);
+        *a = merged;
+        did_merge
+    }
+
+    fn modify(egraph: &mut EGraph<MigLanguage, Self>, id: Id) {
+        let value = egraph[id].data.constant;
+        ConstantFolding::merge_constant(egraph, id, value);
+    }
+}
+
+fn maj(a: &ClassFunction, b: &ClassFunction, c: &ClassFunction) -> ClassFunction {
+    let signature = [0, 1, 2, 3].map(|lane| {
+        let [a, b, c] = [a, b, c].map(|function| function.signature[lane]);
+        (a & b) | (a & c) | (b & c)
+    });
+    let support = match (&a.support, &b.support, &c.support) {
+        (Some(a), Some(b), Some(c)) => {
+            let mut support: Support = a.iter().chain(b).chain(c).copied().collect();
+            support.sort_unstable();
+            support.dedup();
+            Some(support).filter(|support| support.len() <= MAX_EXHAUSTIVE_INPUTS)
+        }
+        _ => None,
+    };
+    let (support, table) = match (support, a.table, b.table, c.table) {
+        (Some(support), Some(_), Some(_), Some(_)) if support.len() <= MAX_TABLE_SUPPORT => {
+            let [ta, tb, tc] = [a, b, c].map(|function| {
+                remap(
+                    &function.table.unwrap(),
+                    function.support.as_ref().unwrap(),
+                    &support,
+                )
+            });
+            let table = [0, 1, 2, 3]
+                .map(|lane| (ta[lane] & tb[lane]) | (ta[lane] & tc[lane]) | (tb[lane] & tc[lane]));
+            let (support, table) = shrink(&support, &table);
+            (Some(support), Some(table))
+        }
+        (support, ..) => (support, None),
+    };
+    let constant = table
+        .filter(|_| support.as_ref().is_some_and(|support| support.is_empty()))
+        .map(|table| table[0] & 1 == 1);
+    ClassFunction {
+        constant,
+        signature,
+        support,
+        table,
+    }
+}
+
+/// Returns the truth table over `to` of the function with the given truth table over `from`. The
+/// function must not depend on the inputs of `from` missing from `to`.
+fn remap(table: &Bits, from: &[u64], to: &[u64]) -> Bits {
+    // the position within `to` of each input of `from`
+    let positions: SmallVec<[Option<usize>; MAX_TABLE_SUPPORT]> = from
+        .iter()
+        .map(|input| to.iter().position(|other| other == input))
+        .collect();
+    let mut result = [0; LANES];
+    for pattern in 0..LANES * 64 {
+        let index = positions
+            .iter()
+            .enumerate()
+            .filter(|(_, position)| position.is_some_and(|position| (pattern >> position) & 1 == 1))
+            .fold(0, |index, (i, _)| index | (1 << i));
+        if bit(table, index) {
+            result[pattern / 64] |= 1 << (pattern % 64);
+        }
+    }
+    result
+}
+
+/// Removes the inputs the function does not depend on from its support.
+fn shrink(support: &[u64], table: &Bits) -> (Support, Bits) {
+    let relevant: Support = support
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| {
+            (0..LANES * 64).any(|pattern| bit(table, pattern) != bit(table, pattern ^ (1 << i)))
+        })
+        .map(|(_, input)| *input)
+        .collect();
+    let table = remap(table, support, &relevant);
+    (relevant, table)
+}
+
+fn bit(table: &Bits, pattern: usize) -> bool {
+    (table[pattern / 64] >> (pattern % 64)) & 1 == 1
+}
+
+/// Returns the random patterns of the given input, derived from its index using SplitMix64.
+fn random_bits(input: u64) -> Bits {
+    let mut state = input.wrapping_mul(LANES as u64);
+    [0; LANES].map(|_| {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    })
+}
+
+/// Merges the e-classes proven to compute the same function and returns the number of merges.
+/// Classes with truth tables are equal iff their supports and truth tables are. Other classes with
+/// equal signatures are simulated on all patterns of their combined support, if it has at most
+/// [MAX_EXHAUSTIVE_INPUTS] inputs, against the first class of each group of equivalent classes
+/// found so far.
+pub fn merge_equivalent_classes(egraph: &mut EGraph<MigLanguage, FunctionalAnalysis>) -> usize {
+    let mut equivalent = Vec::new();
+    let mut tables = FxHashMap::default();
+    let mut signatures: FxHashMap<Bits, Vec<Id>> = FxHashMap::default();
+    for class in egraph.classes() {
+        let function = &class.data;
+        match (&function.support, function.table) {
+            (Some(support), Some(table)) => {
+                if let Some(other) = tables.insert((support.clone(), table), class.id) {
+                    equivalent.push((other, class.id));
+                }
+            }
+            (Some(_), None) => signatures
+                .entry(function.signature)
+                .or_default()
+                .push(class.id),
+            (None, _) => {}
+        }
+    }
+
+    let candidates: Vec<Vec<Id>> = signatures
+        .into_values()
+        .filter(|ids| ids.len() > 1)
+        .collect();
+    if !candidates.is_empty() {
+        let extractor = Extractor::new(egraph, AstSize);
+        for ids in candidates {
+            // partition the classes into groups of equivalent ones, each represented by its first
+            // class, so that equivalences not involving the first class of the bucket are found
+            let mut representatives: Vec<Id> = Vec::new();
+            for id in ids {
+                match representatives
+                    .iter()
+                    .find(|representative| simulate_equal(egraph, &extractor, **representative, id))
+                {
+                    Some(&representative) => equivalent.push((representative, id)),
+                    None => representatives.push(id),
+                }
+            }
+        }
+    }
+
+    let merges = equivalent
+        .into_iter()
+        .filter(|(a, b)| egraph.union(*a, *b))
+        .count();
+    egraph.rebuild();
+    merges
+}
+
+/// Checks whether the two classes are equal on all patterns of their combined support, simulating
+/// the nodes chosen by the extractor.
+fn simulate_equal(
+    egraph: &EGraph<MigLanguage, FunctionalAnalysis>,
+    extractor: &Extractor<AstSize, MigLanguage, FunctionalAnalysis>,
+    a: Id,
+    b: Id,
+) -> bool {
+    let (Some(support_a), Some(support_b)) = (&egraph[a].data.support, &egraph[b].data.support)
+    else {
+        return false;
+    };
+    let mut support: Vec<u64> = support_a.iter().chain(support_b).copied().collect();
+    support.sort_unstable();
+    support.dedup();
+    if support.len() > MAX_EXHAUSTIVE_INPUTS {
+        return false;
+    }
+    let patterns = patterns(support.len());
+    let lanes = patterns.first().map_or(1, Vec::len);
+    // the classes do not depend on inputs outside the support, so their value does not matter
+    let values = simulate_classes(
+        egraph,
+        &[a, b],
+        lanes,
+        |id| extractor.find_best_node(id),
+        |input| match support.iter().position(|other| *other == input) {
+            Some(position) => patterns[position].clone(),
+            None => vec![0; lanes],
+        },
+    );
+    values[0] == values[1]
+}
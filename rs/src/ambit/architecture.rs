@@ -0,0 +1,416 @@
+use super::{BitwiseRow, CostModel};
+use rustc_hash::FxHashSet;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum BitwiseOperand {
+    T(u8),
+    DCC { inverted: bool, index: u8 },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "ArchitectureDescription", into = "ArchitectureDescription")]
+pub struct Architecture {
+    pub(super) maj_ops: Vec<usize>,
+    pub(super) multi_activations: Vec<Vec<BitwiseOperand>>,
+    pub(super) num_dcc: u8,
+    pub(super) cost_model: CostModel,
+}
+
+/// The serialized form of an [Architecture]. `maj_ops` is always derived from the activations,
+/// `num_dcc` defaults to the number of DCC rows referenced by them and parameters of the
+/// `cost_model` that are not given default to those of [CostModel::default].
+///
+/// Operands are written in the same syntax as used when printing programs, e.g.
+///
+/// ```toml
+/// multi_activations = [
+///     ["~DCC0", "T0"],
+///     ["T0", "T1", "T2"],
+/// ]
+///
+/// [cost_model]
+/// t_ras = 35.0
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ArchitectureDescription {
+    multi_activations: Vec<Vec<BitwiseOperand>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    num_dcc: Option<u8>,
+    #[serde(default)]
+    cost_model: CostModel,
+}
+
+/// A violation of an invariant the compiler relies on. Activations are referred to by their index
+/// in `multi_activations`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ArchitectureError {
+    /// There is no activation of an odd number of at least three rows, i.e. no MAJ can be computed.
+    NoMajOperation,
+    /// The activation does not activate any row.
+    EmptyActivation { activation: usize },
+    /// The activation references a DCC row that is not below `num_dcc`.
+    DccOutOfRange { activation: usize, index: u8 },
+    /// The activation contains the same row more than once.
+    DuplicateRow { activation: usize, row: BitwiseRow },
+    /// Every MAJ operation with the most operands, i.e. `rows`, uses all DCC rows, leaving none to
+    /// invert operands with when a node requires that many operands.
+    NoFreeDcc { rows: usize },
+    /// The T row is declared by some activation but is not an operand of any MAJ operation.
+    UnreachableT { index: u8 },
+    /// The parameter of the cost model is negative or not finite.
+    InvalidCost { parameter: &'static str },
+}
+
+/// All errors found by [Architecture::validate].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidArchitecture(pub Vec<ArchitectureError>);
+
+#[derive(Debug)]
+pub enum ArchitectureLoadError {
+    Io(PathBuf, std::io::Error),
+    Toml(PathBuf, toml::de::Error),
+    Json(PathBuf, serde_json::Error),
+    UnknownFormat(PathBuf),
+    Invalid(PathBuf, InvalidArchitecture),
+}
+
+impl Architecture {
+    pub fn new(
+        multi_activations: Vec<Vec<BitwiseOperand>>,
+        num_dcc: u8,
+    ) -> Result<Self, InvalidArchitecture> {
+        // activating an odd number of (at least three) rows computes the majority of their values
+        let maj_ops = multi_activations
+            .iter()
+            .enumerate()
+            .filter(|(_, ops)| ops.len() >= 3 && ops.len() % 2 == 1)
+            .map(|(i, _)| i)
+            .collect();
+        let architecture = Self {
+            maj_ops,
+            multi_activations,
+            num_dcc,
+            cost_model: CostModel::default(),
+        };
+        architecture.validate()?;
+        Ok(architecture)
+    }
+
+    /// Replaces the cost model used to estimate the latency and energy of programs.
+    pub fn with_cost_model(mut self, cost_model: CostModel) -> Result<Self, InvalidArchitecture> {
+        self.cost_model = cost_model;
+        self.validate()?;
+        Ok(self)
+    }
+
+    /// Checks that this architecture can be compiled for, returning all problems found otherwise.
+    pub fn validate(&self) -> Result<(), InvalidArchitecture> {
+        let mut errors = Vec::new();
+        if self.maj_ops.is_empty() {
+            errors.push(ArchitectureError::NoMajOperation);
+        }
+        for (activation, operands) in self.multi_activations.iter().enumerate() {
+            if operands.is_empty() {
+                errors.push(ArchitectureError::EmptyActivation { activation });
+            }
+            let mut rows = FxHashSet::default();
+            for operand in operands {
+                if let BitwiseOperand::DCC { index, .. } = *operand {
+                    if index >= self.num_dcc {
+                        errors.push(ArchitectureError::DccOutOfRange { activation, index });
+                    }
+                }
+                if !rows.insert(operand.row()) {
+                    errors.push(ArchitectureError::DuplicateRow {
+                        activation,
+                        row: operand.row(),
+                    });
+                }
+            }
+        }
+        // the compiler only uses MAJ operations occupying all DCC rows if no operand has to be
+        // copied, so each node has to fit into one that does not
+        let rows = self.max_maj_operands();
+        let has_free_dcc = self.maj_ops.iter().any(|&activation| {
+            let operands = &self.multi_activations[activation];
+            operands.len() == rows && self.free_dcc(operands).is_some()
+        });
+        if !self.maj_ops.is_empty() && !has_free_dcc {
+            errors.push(ArchitectureError::NoFreeDcc { rows });
+        }
+        let mut unreachable_t: Vec<u8> = self
+            .multi_activations
+            .iter()
+            .flatten()
+            .filter_map(|op| match op {
+                BitwiseOperand::T(t) => Some(*t),
+                _ => None,
+            })
+            .filter(|t| {
+                !self
+                    .maj_ops
+                    .iter()
+                    .any(|&maj| self.multi_activations[maj].contains(&BitwiseOperand::T(*t)))
+            })
+            .collect();
+        unreachable_t.sort();
+        unreachable_t.dedup();
+        errors.extend(
+            unreachable_t
+                .into_iter()
+                .map(|index| ArchitectureError::UnreachableT { index }),
+        );
+        errors.extend(
+            self.cost_model
+                .invalid_parameters()
+                .map(|parameter| ArchitectureError::InvalidCost { parameter }),
+        );
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(InvalidArchitecture(errors))
+        }
+    }
+
+    /// Returns the maximum number of operands of a MAJ operation.
+    pub fn max_maj_operands(&self) -> usize {
+        self.maj_ops
+            .iter()
+            .map(|op| self.multi_activations[*op].len())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Returns a DCC row that is not among the given operands, if any.
+    pub fn free_dcc(&self, operands: &[BitwiseOperand]) -> Option<u8> {
+        (0..self.num_dcc).find(|i| {
+            !operands
+                .iter()
+                .any(|op| matches!(op, BitwiseOperand::DCC { index, .. } if index == i))
+        })
+    }
+
+    /// Loads an architecture description from the given file. The format is determined by the file
+    /// extension, which has to be either `.toml` or `.json`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ArchitectureLoadError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| ArchitectureLoadError::Io(path.into(), err))?;
+        // deserialize the description first so that validation errors keep their type
+        let description: ArchitectureDescription =
+            match path.extension().and_then(|ext| ext.to_str()) {
+                Some("toml") => toml::from_str(&content)
+                    .map_err(|err| ArchitectureLoadError::Toml(path.into(), err))?,
+                Some("json") => serde_json::from_str(&content)
+                    .map_err(|err| ArchitectureLoadError::Json(path.into(), err))?,
+                _ => return Err(ArchitectureLoadError::UnknownFormat(path.into())),
+            };
+        description
+            .try_into()
+            .map_err(|err| ArchitectureLoadError::Invalid(path.into(), err))
+    }
+}
+
+impl TryFrom<ArchitectureDescription> for Architecture {
+    type Error = InvalidArchitecture;
+
+    fn try_from(value: ArchitectureDescription) -> Result<Self, Self::Error> {
+        let num_dcc = value.num_dcc.unwrap_or_else(|| {
+            value
+                .multi_activations
+                .iter()
+                .flatten()
+                .filter_map(|op| match op {
+                    BitwiseOperand::DCC { index, .. } => Some(index + 1),
+                    _ => None,
+                })
+                .max()
+                .unwrap_or(0)
+        });
+        Architecture::new(value.multi_activations, num_dcc)?.with_cost_model(value.cost_model)
+    }
+}
+
+impl From<Architecture> for ArchitectureDescription {
+    fn from(value: Architecture) -> Self {
+        Self {
+            multi_activations: value.multi_activations,
+            num_dcc: Some(value.num_dcc),
+            cost_model: value.cost_model,
+        }
+    }
+}
+
+impl BitwiseOperand {
+    pub fn row(&self) -> BitwiseRow {
+        match self {
+            BitwiseOperand::T(t) => BitwiseRow::T(*t),
+            BitwiseOperand::DCC { index, .. } => BitwiseRow::DCC(*index),
+        }
+    }
+    pub fn is_dcc(&self) -> bool {
+        matches!(self, BitwiseOperand::DCC { .. })
+    }
+    pub fn inverted(&self) -> bool {
+        matches!(self, BitwiseOperand::DCC { inverted: true, .. })
+    }
+}
+
+impl Display for BitwiseOperand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BitwiseOperand::T(t) => write!(f, "T{t}"),
+            BitwiseOperand::DCC { inverted, index } => {
+                if *inverted {
+                    write!(f, "~DCC{index}")
+                } else {
+                    write!(f, "DCC{index}")
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for BitwiseOperand {
+    type Err = String;
+
+    /// Parses operands in the format used by [Display], i.e. `T<i>`, `DCC<i>` or `~DCC<i>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid bitwise operand `{s}`");
+        let parse_index = |idx: &str| idx.parse::<u8>().map_err(|_| invalid());
+        if let Some(idx) = s.strip_prefix("~DCC") {
+            Ok(BitwiseOperand::DCC {
+                inverted: true,
+                index: parse_index(idx)?,
+            })
+        } else if let Some(idx) = s.strip_prefix("DCC") {
+            Ok(BitwiseOperand::DCC {
+                inverted: false,
+                index: parse_index(idx)?,
+            })
+        } else if let Some(idx) = s.strip_prefix("T") {
+            Ok(BitwiseOperand::T(parse_index(idx)?))
+        } else {
+            Err(invalid())
+        }
+    }
+}
+
+impl Serialize for BitwiseOperand {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for BitwiseOperand {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl Display for ArchitectureLoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(path, err) => write!(f, "could not read {}: {err}", path.display()),
+            Self::Toml(path, err) => write!(f, "invalid architecture in {}: {err}", path.display()),
+            Self::Json(path, err) => write!(f, "invalid architecture in {}: {err}", path.display()),
+            Self::UnknownFormat(path) => write!(
+                f,
+                "unknown architecture format of {} (expected .toml or .json)",
+                path.display()
+            ),
+            Self::Invalid(path, err) => write!(f, "{} describes an {err}", path.display()),
+        }
+    }
+}
+
+impl Display for ArchitectureError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoMajOperation => write!(
+                f,
+                "no activation of an odd number of rows to compute MAJ with"
+            ),
+            Self::EmptyActivation { activation } => {
+                write!(f, "activation {activation} does not activate any row")
+            }
+            Self::DccOutOfRange { activation, index } => write!(
+                f,
+                "activation {activation} uses DCC{index}, which does not exist"
+            ),
+            Self::DuplicateRow { activation, row } => write!(
+                f,
+                "activation {activation} uses {} more than once",
+                BitwiseOperand::from(*row)
+            ),
+            Self::NoFreeDcc { rows } => write!(
+                f,
+                "every MAJ activation of {rows} rows uses all DCC rows, none is left for inverting \
+                operands"
+            ),
+            Self::UnreachableT { index } => {
+                write!(f, "T{index} is not an operand of any MAJ activation")
+            }
+            Self::InvalidCost { parameter } => {
+                write!(
+                    f,
+                    "cost model parameter {parameter} has to be a non-negative number"
+                )
+            }
+        }
+    }
+}
+
+impl Display for InvalidArchitecture {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid architecture:")?;
+        for err in &self.0 {
+            write!(f, "\n  - {err}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for InvalidArchitecture {}
+
+impl std::error::Error for ArchitectureLoadError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use BitwiseOperand::*;
+
+    const DCC0: BitwiseOperand = DCC {
+        index: 0,
+        inverted: false,
+    };
+
+    #[test]
+    fn dcc_saturating_activation_is_accepted() {
+        let architecture =
+            Architecture::new(vec![vec![T(0), T(1), T(2)], vec![DCC0, T(1), T(2)]], 1);
+        assert!(architecture.is_ok());
+    }
+
+    #[test]
+    fn no_free_dcc_is_rejected() {
+        let err = Architecture::new(vec![vec![DCC0, T(1), T(2)]], 1).unwrap_err();
+        assert_eq!(err.0, [ArchitectureError::NoFreeDcc { rows: 3 }]);
+
+        // smaller activations keeping a DCC row free do not help nodes requiring five operands
+        let err = Architecture::new(
+            vec![vec![T(0), T(1), T(2)], vec![DCC0, T(0), T(1), T(2), T(3)]],
+            1,
+        )
+        .unwrap_err();
+        assert_eq!(err.0, [ArchitectureError::NoFreeDcc { rows: 5 }]);
+    }
+}
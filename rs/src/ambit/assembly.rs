@@ -0,0 +1,265 @@
+//! Parser for the textual representation of programs as printed by `Display for Program`:
+//!
+//! ```text
+//! program     = { line }
+//! line        = [ instruction ] [ comment ] "\n"
+//! instruction = "AAP" address address | "AP" address
+//! address     = "I" u64 | "O" u64 | "S" u32 | "C0" | "C1" | operand
+//!             | "[" operand { "," operand } "]"
+//! operand     = "T" u8 | "DCC" u8 | "~DCC" u8
+//! comment     = "#" { any character }
+//! ```
+//!
+//! Tokens are separated by whitespace, which is also allowed within operand lists. An operand list
+//! refers to the multi-row activation of the architecture that activates exactly the listed
+//! operands, in any order, and must not list an operand more than once.
+
+use super::{Address, Architecture, BitwiseAddress, BitwiseOperand, Instruction, Program};
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// 1-based number of the line containing the error
+    pub line: usize,
+    pub kind: ParseErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    UnknownInstruction(String),
+    MissingAddress,
+    InvalidAddress(String),
+    UnterminatedList,
+    /// The operand is listed more than once.
+    DuplicateOperand(BitwiseOperand),
+    /// The architecture has no multi-row activation of the listed operands.
+    UnknownActivation(Vec<BitwiseOperand>),
+    TrailingInput(String),
+}
+
+impl<'a> Program<'a> {
+    /// Parses a program for the given architecture from its textual representation, see the
+    /// [module documentation](self) for the syntax.
+    pub fn parse(architecture: &'a Architecture, source: &str) -> Result<Self, ParseError> {
+        Ok(Self::new(
+            architecture,
+            parse_instructions(architecture, source)?,
+        ))
+    }
+}
+
+/// Parses the instructions of a program, see the [module documentation](self) for the syntax.
+pub fn parse_instructions(
+    architecture: &Architecture,
+    source: &str,
+) -> Result<Vec<Instruction>, ParseError> {
+    let mut instructions = Vec::new();
+    for (idx, line) in source.lines().enumerate() {
+        let line_content = line.split_once('#').map_or(line, |(content, _)| content);
+        let error = |kind| ParseError {
+            line: idx + 1,
+            kind,
+        };
+        let mut parser = LineParser {
+            architecture,
+            rest: line_content,
+        };
+        let Some(mnemonic) = parser.next_token() else {
+            continue;
+        };
+        let instruction = match mnemonic {
+            "AAP" => Instruction::AAP(
+                parser.address().map_err(error)?,
+                parser.address().map_err(error)?,
+            ),
+            "AP" => Instruction::AP(parser.address().map_err(error)?),
+            _ => {
+                return Err(error(ParseErrorKind::UnknownInstruction(
+                    mnemonic.to_string(),
+                )))
+            }
+        };
+        let rest = parser.rest.trim();
+        if !rest.is_empty() {
+            return Err(error(ParseErrorKind::TrailingInput(rest.to_string())));
+        }
+        instructions.push(instruction);
+    }
+    Ok(instructions)
+}
+
+struct LineParser<'a, 's> {
+    architecture: &'a Architecture,
+    rest: &'s str,
+}
+
+impl<'s> LineParser<'_, 's> {
+    /// Returns the next whitespace-separated token, if any.
+    fn next_token(&mut self) -> Option<&'s str> {
+        let rest = self.rest.trim_start();
+        if rest.is_empty() {
+            return None;
+        }
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let (token, rest) = rest.split_at(end);
+        self.rest = rest;
+        Some(token)
+    }
+
+    fn address(&mut self) -> Result<Address, ParseErrorKind> {
+        self.rest = self.rest.trim_start();
+        if let Some(list) = self.rest.strip_prefix('[') {
+            let (list, rest) = list
+                .split_once(']')
+                .ok_or(ParseErrorKind::UnterminatedList)?;
+            self.rest = rest;
+            return self.multiple(list);
+        }
+        let token = self.next_token().ok_or(ParseErrorKind::MissingAddress)?;
+        let invalid = || ParseErrorKind::InvalidAddress(token.to_string());
+        let address = if token == "C0" {
+            Address::Const(false)
+        } else if token == "C1" {
+            Address::Const(true)
+        } else if let Some(i) = token.strip_prefix('I') {
+            Address::In(i.parse().map_err(|_| invalid())?)
+        } else if let Some(i) = token.strip_prefix('O') {
+            Address::Out(i.parse().map_err(|_| invalid())?)
+        } else if let Some(i) = token.strip_prefix('S') {
+            Address::Spill(i.parse().map_err(|_| invalid())?)
+        } else {
+            token
+                .parse::<BitwiseOperand>()
+                .map_err(|_| invalid())?
+                .into()
+        };
+        Ok(address)
+    }
+
+    /// Resolves the comma-separated list of operands to the multi-row activation activating
+    /// exactly these operands.
+    fn multiple(&self, list: &str) -> Result<Address, ParseErrorKind> {
+        let operands = list
+            .split(',')
+            .map(|operand| {
+                let operand = operand.trim();
+                operand
+                    .parse()
+                    .map_err(|_| ParseErrorKind::InvalidAddress(operand.to_string()))
+            })
+            .collect::<Result<Vec<BitwiseOperand>, _>>()?;
+        for (i, operand) in operands.iter().enumerate() {
+            if operands[..i].contains(operand) {
+                return Err(ParseErrorKind::DuplicateOperand(*operand));
+            }
+        }
+        self.architecture
+            .multi_activations
+            .iter()
+            .position(|activation| {
+                activation.len() == operands.len()
+                    && operands.iter().all(|operand| activation.contains(operand))
+            })
+            .map(|idx| BitwiseAddress::Multiple(idx).into())
+            .ok_or(ParseErrorKind::UnknownActivation(operands))
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            ParseErrorKind::UnknownInstruction(mnemonic) => {
+                write!(f, "unknown instruction `{mnemonic}`")
+            }
+            ParseErrorKind::MissingAddress => write!(f, "missing address"),
+            ParseErrorKind::InvalidAddress(address) => write!(f, "invalid address `{address}`"),
+            ParseErrorKind::UnterminatedList => write!(f, "operand list is missing `]`"),
+            ParseErrorKind::DuplicateOperand(operand) => {
+                write!(f, "operand list contains {operand} more than once")
+            }
+            ParseErrorKind::UnknownActivation(operands) => {
+                write!(f, "the architecture cannot activate [")?;
+                for (i, operand) in operands.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{operand}")?;
+                }
+                write!(f, "] at once")
+            }
+            ParseErrorKind::TrailingInput(input) => write!(f, "unexpected `{input}`"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambit::presets::AMBIT;
+
+    #[test]
+    fn round_trip() {
+        let activation = |operands: &[BitwiseOperand]| {
+            let idx = AMBIT
+                .multi_activations
+                .iter()
+                .position(|activation| activation.as_slice() == operands)
+                .unwrap();
+            Address::Bitwise(BitwiseAddress::Multiple(idx))
+        };
+        let maj = activation(&[
+            BitwiseOperand::T(0),
+            BitwiseOperand::T(1),
+            BitwiseOperand::T(2),
+        ]);
+        let dcc = BitwiseOperand::DCC {
+            index: 0,
+            inverted: true,
+        };
+        let instructions = vec![
+            Instruction::AAP(Address::In(0), BitwiseOperand::T(0).into()),
+            Instruction::AAP(Address::Const(true), BitwiseOperand::T(1).into()),
+            Instruction::AAP(Address::Spill(3), BitwiseOperand::T(2).into()),
+            Instruction::AP(maj),
+            Instruction::AAP(BitwiseOperand::T(0).into(), dcc.into()),
+            Instruction::AAP(maj, Address::Out(1)),
+            Instruction::AAP(Address::Const(false), Address::Spill(0)),
+        ];
+        let program = Program::new(&AMBIT, instructions);
+        let parsed = Program::parse(&AMBIT, &program.to_string()).unwrap();
+        assert_eq!(parsed.instructions, program.instructions);
+    }
+
+    #[test]
+    fn operand_lists_in_any_order() {
+        let program = Program::parse(&AMBIT, "AP [T2, T0, T1]\nAP [T0, T1, T2] # comment").unwrap();
+        assert_eq!(program.instructions[0], program.instructions[1]);
+    }
+
+    #[test]
+    fn duplicate_operands() {
+        let err = Program::parse(&AMBIT, "AP [T0, T1, T2]\nAP [T0, T0, T1]").unwrap_err();
+        assert_eq!(
+            err,
+            ParseError {
+                line: 2,
+                kind: ParseErrorKind::DuplicateOperand(BitwiseOperand::T(0)),
+            }
+        );
+    }
+
+    #[test]
+    fn line_numbered_errors() {
+        let err = Program::parse(&AMBIT, "AP [T0, T1, T2]\n\nAAP I0").unwrap_err();
+        assert_eq!(
+            err,
+            ParseError {
+                line: 3,
+                kind: ParseErrorKind::MissingAddress,
+            }
+        );
+    }
+}
@@ -5,7 +5,9 @@ use super::{
 use crate::ambit::rows::Row;
 use eggmock::{Id, Mig, NetworkWithBackwardEdges, Node, Signal};
 use rustc_hash::{FxHashMap, FxHashSet};
+use smallvec::{smallvec, SmallVec};
 use std::cmp::max;
+use std::fmt::{Display, Formatter};
 
 pub struct CompilationState<'a, 'n, N> {
     network: &'n N,
@@ -16,13 +18,44 @@ pub struct CompilationState<'a, 'n, N> {
 
     outputs: FxHashSet<Id>,
     leftover_use_count: FxHashMap<Id, usize>,
+
+    /// contains the inputs of all MAJ5 clusters (see [find_maj5_clusters]) by their root node
+    maj5_clusters: FxHashMap<Id, [Signal; 5]>,
+    /// maps the inner nodes of MAJ5 clusters, which are never computed on their own, to the root
+    /// node of their cluster
+    absorbed: FxHashMap<Id, Id>,
+}
+
+/// The reason why a network could not be compiled, referring to the node of the network at which
+/// compilation failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompileError {
+    /// The leaf node is neither an input nor the constant.
+    InvalidLeaf { node: Id },
+    /// The row should be empty when storing the node in it, e.g. because two input nodes share the
+    /// same index.
+    RowOccupied { node: Id, row: Row },
+    /// The node is computed although some of its inputs are not yet computed or it has already been
+    /// computed, e.g. because it is used by more than one output.
+    NotACandidate { node: Id },
+    /// The node is scheduled for computation, but is not a MAJ node.
+    NotAMaj { node: Id },
+    /// No MAJ operation of the architecture has enough operands for the inputs of the node.
+    NoMajOperation { node: Id, inputs: usize },
+    /// The MAJ operation used for the node occupies all DCC rows, so operands cannot be inverted.
+    NoFreeDcc { node: Id, activation: usize },
+    /// The signal has to be copied, but neither it nor its inversion is stored in any row.
+    SignalUnavailable { signal: Signal },
+    /// The extractor selected no node for the e-class, which the outputs depend on, so the network
+    /// to compile is incomplete.
+    NotExtractable { node: Id },
 }
 
 pub fn compile<'a>(
     architecture: &'a Architecture,
     network: &impl NetworkWithBackwardEdges<Node = Mig>,
-) -> Result<Program<'a>, &'static str> {
-    let mut state = CompilationState::new(architecture, network);
+) -> Result<Program<'a>, CompileError> {
+    let mut state = CompilationState::new(architecture, network)?;
     let mut max_cand_size = 0;
     while !state.candidates.is_empty() {
         max_cand_size = max(max_cand_size, state.candidates.len());
@@ -33,8 +66,8 @@ pub fn compile<'a>(
             .map(|(id, node)| {
                 let outputs = state.network.node_outputs(id).count();
                 let output = state.network.outputs().any(|out| out.node_id() == id);
-                let not_present = node
-                    .inputs()
+                let not_present = state
+                    .maj_inputs(id, node)
                     .iter()
                     .map(|signal| {
                         let present = state
@@ -55,14 +88,14 @@ pub fn compile<'a>(
                     continue;
                 }
                 if signal.is_inverted() {
-                    state.compute(id, node, None);
+                    state.compute(id, node, None)?;
                     state.program.signal_copy(
                         signal,
                         SingleRowAddress::Out(output as u64),
                         state.program.rows().get_free_dcc().unwrap_or(0),
-                    );
+                    )?;
                 } else {
-                    state.compute(id, node, Some(Address::Out(output as u64)));
+                    state.compute(id, node, Some(Address::Out(output as u64)))?;
                 }
             }
             state.outputs.remove(&id);
@@ -71,7 +104,7 @@ pub fn compile<'a>(
                 state.program.free_id_rows(id);
             }
         } else {
-            state.compute(id, node, None);
+            state.compute(id, node, None)?;
         }
     }
     // outputs that are directly derived from inputs will not be computed by the loop above
@@ -82,7 +115,7 @@ pub fn compile<'a>(
         }
         state
             .program
-            .signal_copy(output_sig, SingleRowAddress::Out(idx as u64), 0);
+            .signal_copy(output_sig, SingleRowAddress::Out(idx as u64), 0)?;
     }
     let mut program = state.program.into();
     optimize(&mut program);
@@ -90,96 +123,144 @@ pub fn compile<'a>(
 }
 
 impl<'a, 'n, N: NetworkWithBackwardEdges<Node = Mig>> CompilationState<'a, 'n, N> {
-    pub fn new(architecture: &'a Architecture, network: &'n N) -> Self {
-        let mut candidates = FxHashSet::default();
+    pub fn new(architecture: &'a Architecture, network: &'n N) -> Result<Self, CompileError> {
+        let program = ProgramState::new(architecture, network)?;
+        let outputs = network.outputs().map(|sig| sig.node_id()).collect();
+        let (maj5_clusters, absorbed) = if architecture.max_maj_operands() >= 5 {
+            find_maj5_clusters(network, &outputs)
+        } else {
+            Default::default()
+        };
+        let mut state = Self {
+            network,
+            candidates: FxHashSet::default(),
+            program,
+            outputs,
+            leftover_use_count: FxHashMap::default(),
+            maj5_clusters,
+            absorbed,
+        };
         // check all parents of leafs whether they have only leaf children, in which case they are
         // candidates
         for leaf in network.leafs() {
-            for candidate_id in network.node_outputs(leaf) {
+            let parents: SmallVec<[Id; 4]> = state.parents(leaf).collect();
+            for candidate_id in parents {
                 let candidate = network.node(candidate_id);
-                if candidate
-                    .inputs()
+                if state
+                    .maj_inputs(candidate_id, candidate)
                     .iter()
                     .all(|signal| network.node(signal.node_id()).is_leaf())
                 {
-                    candidates.insert((candidate_id, candidate));
+                    state.candidates.insert((candidate_id, candidate));
                 }
             }
         }
-        let program = ProgramState::new(architecture, network);
-        let outputs = network.outputs().map(|sig| sig.node_id()).collect();
-        Self {
-            network,
-            candidates,
-            program,
-            outputs,
-            leftover_use_count: FxHashMap::default(),
-        }
+        Ok(state)
     }
 
     pub fn leftover_use_count(&mut self, id: Id) -> &mut usize {
-        self.leftover_use_count.entry(id).or_insert_with(|| {
-            self.network.node_outputs(id).count() + self.outputs.contains(&id) as usize
-        })
+        if !self.leftover_use_count.contains_key(&id) {
+            // inputs are used only once per parent, see `compute`
+            let parents: FxHashSet<Id> = self.parents(id).collect();
+            let count = parents.len() + self.outputs.contains(&id) as usize;
+            self.leftover_use_count.insert(id, count);
+        }
+        self.leftover_use_count.get_mut(&id).unwrap()
+    }
+
+    /// Returns the nodes using the given node, where inner nodes of MAJ5 clusters are replaced by
+    /// the root node of their cluster.
+    fn parents(&self, id: Id) -> impl Iterator<Item = Id> + '_ {
+        self.network
+            .node_outputs(id)
+            .map(|parent| self.absorbed.get(&parent).copied().unwrap_or(parent))
     }
 
-    pub fn compute(&mut self, id: Id, node: Mig, out_address: Option<Address>) {
+    /// Returns the inputs of the given node or, if it is the root of a MAJ5 cluster, the inputs of
+    /// the whole cluster.
+    fn maj_inputs(&self, id: Id, node: Mig) -> SmallVec<[Signal; 5]> {
+        match self.maj5_clusters.get(&id) {
+            Some(inputs) => SmallVec::from_slice(inputs),
+            None => SmallVec::from_slice(node.inputs()),
+        }
+    }
+
+    pub fn compute(
+        &mut self,
+        id: Id,
+        node: Mig,
+        out_address: Option<Address>,
+    ) -> Result<(), CompileError> {
         if !self.candidates.remove(&(id, node)) {
-            panic!("not a candidate");
+            return Err(CompileError::NotACandidate { node: id });
         }
-        let Mig::Maj(mut signals) = node else {
-            panic!("can only compute majs")
+        let Mig::Maj(_) = node else {
+            return Err(CompileError::NotAMaj { node: id });
         };
+        let inputs = self.maj_inputs(id, node);
 
         // select which MAJ instruction to use
         // for this we use the operation with has the most already correctly placed operands
         let mut opt = None;
+        let mut signals = SmallVec::new();
         for id in self.architecture().maj_ops.iter().copied() {
-            let operands = self.architecture().multi_activations[id]
-                .as_slice()
-                .try_into()
-                .expect("maj has to have 3 operands");
+            let operands = self.architecture().multi_activations[id].as_slice();
+            if operands.len() < inputs.len() {
+                continue;
+            }
+            // keep the order found for the previous operation if possible
+            if signals.len() != operands.len() {
+                signals = pad_inputs(&inputs, operands.len());
+            }
             let (matches, match_no) = self.get_mapping(&mut signals, operands);
+            // copying the remaining operands may require inverting them via a DCC row
+            if match_no < operands.len() && self.architecture().free_dcc(operands).is_none() {
+                continue;
+            }
             let dcc_cost = self.optimize_dcc_usage(&mut signals, operands, &matches);
             let spilling_cost = self.spilling_cost(operands, &matches);
-            let cost = 3.0 - match_no as f32 + dcc_cost as f32 + 0.5 * spilling_cost as f32;
+            let cost = operands.len() as f32 - match_no as f32
+                + dcc_cost as f32
+                + 0.5 * spilling_cost as f32;
             let is_opt = match &opt {
                 None => true,
                 Some((opt_no, _, _, _)) => *opt_no > cost,
             };
             if is_opt {
-                opt = Some((cost, id, matches, signals));
+                opt = Some((cost, id, matches, signals.clone()));
             }
         }
-        let (_, maj_id, matches, signals) = opt.unwrap();
+        let (_, maj_id, matches, signals) = opt.ok_or(CompileError::NoMajOperation {
+            node: id,
+            inputs: inputs.len(),
+        })?;
         let operands = &self.architecture().multi_activations[maj_id];
 
         // now we need to place the remaining non-matching operands...
 
         // for that we first find a free DCC row for possibly inverting missing signals without
         // accidentally overriding a signal that is already placed correctly
-        let used_dcc = || {
-            operands.iter().filter_map(|op| match op {
-                BitwiseOperand::DCC { index: i, .. } => Some(i),
-                _ => None,
-            })
-        };
-        let free_dcc = (0..self.architecture().num_dcc)
-            .find(|i| !used_dcc().any(|used| *used == *i))
-            .expect("cannot use all DCC rows in one MAJ operation");
+        let free_dcc = self.architecture().free_dcc(operands);
 
         // then we can copy the signals into their places
-        for i in 0..3 {
+        for i in 0..operands.len() {
             if matches[i] {
                 continue;
             }
-            self.program
-                .signal_copy(signals[i], SingleRowAddress::Bitwise(operands[i]), free_dcc);
+            let free_dcc = free_dcc.ok_or(CompileError::NoFreeDcc {
+                node: id,
+                activation: maj_id,
+            })?;
+            self.program.signal_copy(
+                signals[i],
+                SingleRowAddress::Bitwise(operands[i]),
+                free_dcc,
+            )?;
         }
 
         // all signals are in place, now we can perform the MAJ operation
         self.program
-            .maj(maj_id, Signal::new(id, false), out_address);
+            .maj(maj_id, Signal::new(id, false), out_address)?;
 
         // free up rows if possible
         // (1) for the MAJ-signal
@@ -187,41 +268,43 @@ impl<'a, 'n, N: NetworkWithBackwardEdges<Node = Mig>> CompilationState<'a, 'n, N
             self.program.free_id_rows(id);
         }
         // (2) for the input signals
-        'outer: for i in 0..3 {
+        'outer: for i in 0..inputs.len() {
             // decrease use count only once per id
             for j in 0..i {
-                if signals[i].node_id() == signals[j].node_id() {
+                if inputs[i].node_id() == inputs[j].node_id() {
                     continue 'outer;
                 }
             }
-            *self.leftover_use_count(signals[i].node_id()) -= 1
+            *self.leftover_use_count(inputs[i].node_id()) -= 1
         }
 
         // lastly, determine new candidates
-        for parent_id in self.network.node_outputs(id) {
+        let parents: SmallVec<[Id; 4]> = self.parents(id).collect();
+        for parent_id in parents {
             let parent_node = self.network.node(parent_id);
-            if parent_node
-                .inputs()
+            if self
+                .maj_inputs(parent_id, parent_node)
                 .iter()
                 .all(|s| self.program.rows().contains_id(s.node_id()))
             {
                 self.candidates.insert((parent_id, parent_node));
             }
         }
+        Ok(())
     }
 
     fn optimize_dcc_usage(
         &self,
-        signals: &mut [Signal; 3],
-        operands: &[BitwiseOperand; 3],
-        matching: &[bool; 3],
+        signals: &mut [Signal],
+        operands: &[BitwiseOperand],
+        matching: &[bool],
     ) -> i32 {
         // first, try using a DCC row for all non-matching rows that require inversion
-        let mut dcc_adjusted = [false; 3];
+        let mut dcc_adjusted: SmallVec<[bool; 5]> = smallvec![false; signals.len()];
         let mut changed = true;
         while changed {
             changed = false;
-            for i in 0..3 {
+            for i in 0..signals.len() {
                 if matching[i]
                     || operands[i].is_dcc()
                     || self.program.rows().get_rows(signals[i]).next().is_some()
@@ -230,7 +313,7 @@ impl<'a, 'n, N: NetworkWithBackwardEdges<Node = Mig>> CompilationState<'a, 'n, N
                 }
                 // the i-th operand needs inversion. let's try doing this by swapping it with a
                 // signal of a DCC row so that we require one less copy operation
-                for j in 0..3 {
+                for j in 0..signals.len() {
                     if i == j || matching[j] || dcc_adjusted[j] {
                         continue;
                     }
@@ -257,9 +340,9 @@ impl<'a, 'n, N: NetworkWithBackwardEdges<Node = Mig>> CompilationState<'a, 'n, N
         cost
     }
 
-    fn spilling_cost(&self, operands: &[BitwiseOperand; 3], matching: &[bool; 3]) -> i32 {
+    fn spilling_cost(&self, operands: &[BitwiseOperand], matching: &[bool]) -> i32 {
         let mut cost = 0;
-        for i in 0..3 {
+        for i in 0..operands.len() {
             if matching[i] {
                 continue;
             }
@@ -292,29 +375,29 @@ impl<'a, 'n, N: NetworkWithBackwardEdges<Node = Mig>> CompilationState<'a, 'n, N
     /// signal and the number is equal to the number of trues in the array.
     fn get_mapping(
         &self,
-        signals: &mut [Signal; 3],
-        operands: &[BitwiseOperand; 3],
-    ) -> ([bool; 3], usize) {
-        let signals_with_idx = {
-            let mut i = 0;
-            signals.map(|signal| {
-                i += 1;
-                (signal, i - 1)
-            })
-        };
-        let operand_signals = operands.map(|op| self.program.rows().get_operand_signal(op));
+        signals: &mut [Signal],
+        operands: &[BitwiseOperand],
+    ) -> (SmallVec<[bool; 5]>, usize) {
+        let operand_signals: SmallVec<[Option<Signal>; 5]> = operands
+            .iter()
+            .map(|op| self.program.rows().get_operand_signal(*op))
+            .collect();
 
         // reorder signals by how often their signal is already available in an operand
-        let mut signals_with_matches = signals_with_idx.map(|(s, i)| {
-            (
-                s,
-                i,
-                operand_signals
-                    .iter()
-                    .filter(|sig| **sig == Some(s))
-                    .count(),
-            )
-        });
+        let mut signals_with_matches: SmallVec<[(Signal, usize, usize); 5]> = signals
+            .iter()
+            .enumerate()
+            .map(|(i, s)| {
+                (
+                    *s,
+                    i,
+                    operand_signals
+                        .iter()
+                        .filter(|sig| **sig == Some(*s))
+                        .count(),
+                )
+            })
+            .collect();
         signals_with_matches.sort_by(|a, b| a.2.cmp(&b.2));
 
         // then we can assign places one by one and get an optimal mapping (probably, proof by
@@ -322,9 +405,9 @@ impl<'a, 'n, N: NetworkWithBackwardEdges<Node = Mig>> CompilationState<'a, 'n, N
 
         // contains for each operand index whether the signal at that position is already the
         // correct one
-        let mut result = [false; 3];
+        let mut result: SmallVec<[bool; 5]> = smallvec![false; signals.len()];
         // contains the mapping of old signal index to operand index
-        let mut new_positions = [0usize, 1, 2];
+        let mut new_positions: SmallVec<[usize; 5]> = (0..signals.len()).collect();
         // contains the number of assigned signals (i.e. #true in result)
         let mut assigned_signals = 0;
 
@@ -350,3 +433,348 @@ impl<'a, 'n, N: NetworkWithBackwardEdges<Node = Mig>> CompilationState<'a, 'n, N
         self.program.architecture
     }
 }
+
+/// Pads the inputs of a MAJ to the given (odd) number of operands. A pair of complementary signals
+/// does not change the majority, hence the missing operands are filled with pairs of the first
+/// input and its inversion.
+fn pad_inputs(inputs: &[Signal], operands: usize) -> SmallVec<[Signal; 5]> {
+    let mut signals = SmallVec::from_slice(inputs);
+    while signals.len() < operands {
+        signals.push(inputs[0]);
+        signals.push(inputs[0].invert());
+    }
+    signals
+}
+
+/// Searches the network for MAJ5 operations encoded as `M(e, M(b, c, d), M(a, b, M(a, c, d)))`
+/// where the inner nodes are not used anywhere else, so that each of these clusters can be computed
+/// using a single activation of five rows.
+///
+/// Returns the five inputs by the root node of each cluster and the root node by the inner nodes of
+/// each cluster.
+fn find_maj5_clusters(
+    network: &impl NetworkWithBackwardEdges<Node = Mig>,
+    outputs: &FxHashSet<Id>,
+) -> (FxHashMap<Id, [Signal; 5]>, FxHashMap<Id, Id>) {
+    let mut clusters = FxHashMap::default();
+    let mut absorbed = FxHashMap::default();
+    let mut visited = FxHashSet::default();
+    let mut stack: Vec<Id> = network.outputs().map(|sig| sig.node_id()).collect();
+    while let Some(id) = stack.pop() {
+        if !visited.insert(id) {
+            continue;
+        }
+        let node = network.node(id);
+        stack.extend(node.inputs().iter().map(|sig| sig.node_id()));
+        if absorbed.contains_key(&id) {
+            continue;
+        }
+        let Some((inputs, inner)) = match_maj5(network, outputs, id, node) else {
+            continue;
+        };
+        // clusters must not overlap
+        if inner
+            .iter()
+            .any(|id| absorbed.contains_key(id) || clusters.contains_key(id))
+        {
+            continue;
+        }
+        clusters.insert(id, inputs);
+        absorbed.extend(inner.map(|inner| (inner, id)));
+    }
+    (clusters, absorbed)
+}
+
+/// Checks whether the given node is the root of a MAJ5 cluster (see [find_maj5_clusters]) and
+/// returns the inputs of the MAJ5 and the inner nodes of the cluster if so.
+fn match_maj5(
+    network: &impl NetworkWithBackwardEdges<Node = Mig>,
+    outputs: &FxHashSet<Id>,
+    id: Id,
+    node: Mig,
+) -> Option<([Signal; 5], [Id; 3])> {
+    let Mig::Maj(root) = node else {
+        return None;
+    };
+    // returns the inputs of the node of the given signal if it is a non-inverted MAJ that is used
+    // by the given parent only
+    let inner = |signal: Signal, parent: Id| {
+        if signal.is_inverted()
+            || outputs.contains(&signal.node_id())
+            || !network
+                .node_outputs(signal.node_id())
+                .all(|other| other == parent)
+        {
+            return None;
+        }
+        match network.node(signal.node_id()) {
+            Mig::Maj(inputs) => Some(inputs),
+            _ => None,
+        }
+    };
+    for (p_idx, q_idx, e_idx) in [
+        (0, 1, 2),
+        (0, 2, 1),
+        (1, 0, 2),
+        (1, 2, 0),
+        (2, 0, 1),
+        (2, 1, 0),
+    ] {
+        let (p_id, q_id) = (root[p_idx].node_id(), root[q_idx].node_id());
+        if p_id == q_id {
+            continue;
+        }
+        let (Some(p), Some(q)) = (inner(root[p_idx], id), inner(root[q_idx], id)) else {
+            continue;
+        };
+        for s_idx in 0..3 {
+            let Some(s) = inner(q[s_idx], q_id) else {
+                continue;
+            };
+            let [u, v] = other_signals(q, s_idx);
+            for (a, b) in [(u, v), (v, u)] {
+                // s has to be M(a, c, d) and p has to be M(b, c, d)
+                let Some(idx) = s.iter().position(|sig| *sig == a) else {
+                    continue;
+                };
+                let [c, d] = other_signals(s, idx);
+                if !same_signals(p, [b, c, d]) {
+                    continue;
+                }
+                return Some(([a, b, c, d, root[e_idx]], [p_id, q_id, q[s_idx].node_id()]));
+            }
+        }
+    }
+    None
+}
+
+/// Returns the signals of the given MAJ inputs except for the one at the given index.
+fn other_signals(signals: [Signal; 3], idx: usize) -> [Signal; 2] {
+    [signals[(idx + 1) % 3], signals[(idx + 2) % 3]]
+}
+
+/// Returns true iff both MAJ inputs contain the same signals, ignoring their order.
+fn same_signals(a: [Signal; 3], b: [Signal; 3]) -> bool {
+    let Some(idx) = b.iter().position(|sig| *sig == a[0]) else {
+        return false;
+    };
+    let [b1, b2] = other_signals(b, idx);
+    (a[1] == b1 && a[2] == b2) || (a[1] == b2 && a[2] == b1)
+}
+
+impl Display for CompileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidLeaf { node } => {
+                write!(f, "leaf node {node:?} is neither an input nor a constant")
+            }
+            Self::RowOccupied { node, row } => write!(
+                f,
+                "cannot store node {node:?} in {row:?}, which is already occupied"
+            ),
+            Self::NotACandidate { node } => write!(
+                f,
+                "node {node:?} cannot be computed, it is either computed already or not all of its \
+                inputs are"
+            ),
+            Self::NotAMaj { node } => write!(f, "node {node:?} is not a MAJ node"),
+            Self::NoMajOperation { node, inputs } => write!(
+                f,
+                "the architecture has no MAJ operation with at least {inputs} operands to compute \
+                node {node:?}"
+            ),
+            Self::NoFreeDcc { node, activation } => write!(
+                f,
+                "activation {activation} used for node {node:?} occupies all DCC rows"
+            ),
+            Self::SignalUnavailable { signal } => write!(
+                f,
+                "neither node {:?} nor its inversion is stored in any row",
+                signal.node_id()
+            ),
+            Self::NotExtractable { node } => {
+                write!(f, "no node could be extracted for e-class {node:?}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambit::presets::AMBIT;
+    use crate::ambit::{BitwiseAddress, Instruction};
+    use eggmock::{egg, Network};
+
+    /// A network whose nodes are identified by their index.
+    struct TestNetwork {
+        nodes: Vec<Mig>,
+        outputs: Vec<Signal>,
+    }
+
+    impl Network for TestNetwork {
+        type Node = Mig;
+
+        fn outputs(&self) -> impl Iterator<Item = Signal> {
+            self.outputs.clone().into_iter()
+        }
+
+        fn node(&self, id: Id) -> Mig {
+            self.nodes[usize::from(egg::Id::from(id))]
+        }
+    }
+
+    fn signal(node: usize) -> Signal {
+        Signal::new(egg::Id::from(node).into(), false)
+    }
+
+    fn maj(inputs: [usize; 3]) -> Mig {
+        Mig::Maj(inputs.map(signal))
+    }
+
+    /// The Ambit B-group with an additional activation of five rows.
+    fn maj5_architecture() -> Architecture {
+        use BitwiseOperand::*;
+        let mut multi_activations = AMBIT.multi_activations.clone();
+        multi_activations.push(vec![
+            DCC {
+                index: 0,
+                inverted: false,
+            },
+            T(0),
+            T(1),
+            T(2),
+            T(3),
+        ]);
+        Architecture::new(multi_activations, 2).unwrap()
+    }
+
+    /// Returns the network of `M(e, M(b, c, d), M(a, b, M(a, c, d)))` with the inputs `a` to `e`
+    /// as nodes 1 to 5, the inner nodes as 6 (`M(a, c, d)`), 7 (`M(b, c, d)`) and 8 and the root
+    /// as 9.
+    fn maj5_network() -> TestNetwork {
+        let mut nodes = vec![Mig::False];
+        nodes.extend((0..5).map(Mig::Input));
+        nodes.extend([
+            maj([1, 3, 4]),
+            maj([2, 3, 4]),
+            maj([1, 2, 6]),
+            maj([5, 7, 8]),
+        ]);
+        TestNetwork {
+            nodes,
+            outputs: vec![signal(9)],
+        }
+    }
+
+    fn clusters(network: &TestNetwork) -> FxHashMap<Id, [Signal; 5]> {
+        let outputs = network.outputs().map(|signal| signal.node_id()).collect();
+        find_maj5_clusters(&network.with_backward_edges(), &outputs).0
+    }
+
+    #[test]
+    fn maj5_cluster_is_single_activation() {
+        let network = maj5_network();
+        let clusters = clusters(&network);
+        assert_eq!(clusters.len(), 1);
+        let inputs: FxHashSet<Signal> = clusters[&signal(9).node_id()].into_iter().collect();
+        assert_eq!(inputs, [1, 2, 3, 4, 5].map(signal).into_iter().collect());
+
+        let architecture = maj5_architecture();
+        let program = compile(&architecture, &network.with_backward_edges()).unwrap();
+        let activations: Vec<usize> = program
+            .instructions
+            .iter()
+            .filter_map(|instruction| match *instruction {
+                Instruction::AP(Address::Bitwise(BitwiseAddress::Multiple(activation)))
+                | Instruction::AAP(Address::Bitwise(BitwiseAddress::Multiple(activation)), _) => {
+                    Some(architecture.multi_activations[activation].len())
+                }
+                _ => None,
+            })
+            .filter(|rows| *rows > 2)
+            .collect();
+        assert_eq!(activations, [5]);
+
+        // all 32 patterns of the five inputs
+        let inputs: Vec<Vec<u64>> = (0..5)
+            .map(|i| {
+                vec![(0..32)
+                    .filter(|m| (m >> i) & 1 == 1)
+                    .fold(0, |v, m| v | 1 << m)]
+            })
+            .collect();
+        let expected = (0..32u64)
+            .filter(|m| m.count_ones() >= 3)
+            .fold(0, |v, m| v | 1 << m);
+        assert_eq!(program.simulate(&inputs).unwrap()[&0], vec![expected]);
+    }
+
+    #[test]
+    fn dcc_saturating_activation_is_not_used_for_copies() {
+        use BitwiseOperand::*;
+        let dcc = |inverted| DCC { index: 0, inverted };
+        let architecture = Architecture::new(
+            vec![
+                vec![dcc(true), T(0)],
+                vec![T(0), T(1), T(2)],
+                vec![dcc(false), T(1), T(2)],
+            ],
+            1,
+        )
+        .unwrap();
+        // M(!a, b, c) and M(!M(!a, b, c), b, c)
+        let nodes = vec![
+            Mig::False,
+            Mig::Input(0),
+            Mig::Input(1),
+            Mig::Input(2),
+            Mig::Maj([signal(1).invert(), signal(2), signal(3)]),
+            Mig::Maj([signal(4).invert(), signal(2), signal(3)]),
+        ];
+        let network = TestNetwork {
+            nodes,
+            outputs: vec![signal(5)],
+        };
+        let program = compile(&architecture, &network.with_backward_edges()).unwrap();
+        let [a, b, c] = [0b1111_0000, 0b1100_1100, 0b1010_1010];
+        let maj = |a: u64, b: u64, c: u64| (a & b) | (a & c) | (b & c);
+        let expected = maj(!maj(!a, b, c), b, c);
+        let outputs = program.simulate(&[vec![a], vec![b], vec![c]]).unwrap();
+        assert_eq!(outputs[&0], vec![expected]);
+    }
+
+    #[test]
+    fn shared_inner_node_is_not_clustered() {
+        // M(a, c, d) is also used by M(a, c, e)
+        let mut network = maj5_network();
+        network.nodes.push(maj([1, 3, 6]));
+        network.outputs.push(signal(10));
+        assert!(clusters(&network).is_empty());
+
+        // M(b, c, d) is an output as well
+        let mut network = maj5_network();
+        network.outputs.push(signal(7));
+        assert!(clusters(&network).is_empty());
+    }
+
+    #[test]
+    fn wrong_operand_sharing_is_not_clustered() {
+        // M(e, M(b, c, d), M(a, b, M(a, c, e)))
+        let mut network = maj5_network();
+        network.nodes[6] = maj([1, 3, 5]);
+        assert!(clusters(&network).is_empty());
+
+        // M(e, M(b, c, d), M(a, c, M(a, c, d)))
+        let mut network = maj5_network();
+        network.nodes[8] = maj([1, 3, 6]);
+        assert!(clusters(&network).is_empty());
+
+        // M(e, M(b, c, d), M(a, b, M(a, c, d))) with an inverted M(b, c, d)
+        let mut network = maj5_network();
+        network.nodes[9] = Mig::Maj([signal(5), signal(7).invert(), signal(8)]);
+        assert!(clusters(&network).is_empty());
+    }
+}
@@ -0,0 +1,111 @@
+use super::{Architecture, Instruction, Program};
+use serde::{Deserialize, Serialize};
+
+/// Estimates the latency and energy of instructions on the DRAM. Every instruction activates the
+/// rows of each of its addresses in sequence and precharges the bank afterward, where simultaneously
+/// activating multiple rows takes longer and requires more energy than activating a single row.
+///
+/// The defaults are rough estimates for DDR3-1600 as assumed by Ambit, which reports a triple-row
+/// activation to require 22% more energy than a single-row activation.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CostModel {
+    /// Minimum time (ns) between activating a single row and precharging the bank (tRAS).
+    pub t_ras: f64,
+    /// Additional activation time (ns) for each row that is activated simultaneously.
+    pub t_ras_per_row: f64,
+    /// Time (ns) it takes to precharge the bank (tRP).
+    pub t_rp: f64,
+    /// Energy (nJ) of activating a single row.
+    pub e_act: f64,
+    /// Additional activation energy (nJ) for each row that is activated simultaneously.
+    pub e_act_per_row: f64,
+    /// Energy (nJ) of precharging the bank.
+    pub e_pre: f64,
+}
+
+impl Default for CostModel {
+    fn default() -> Self {
+        Self {
+            t_ras: 35.0,
+            t_ras_per_row: 2.5,
+            t_rp: 13.75,
+            e_act: 1.1,
+            e_act_per_row: 0.121,
+            e_pre: 0.6,
+        }
+    }
+}
+
+impl CostModel {
+    /// Returns the estimated latency (ns) of the given instruction.
+    pub fn latency(&self, instruction: &Instruction, architecture: &Architecture) -> f64 {
+        activated_rows(instruction, architecture)
+            .map(|rows| self.activation_latency(rows))
+            .sum::<f64>()
+            + self.t_rp
+    }
+
+    /// Returns the time (ns) between simultaneously activating the given number of rows and the
+    /// next command.
+    pub fn activation_latency(&self, rows: usize) -> f64 {
+        self.t_ras + (rows - 1) as f64 * self.t_ras_per_row
+    }
+
+    /// Returns the estimated energy (nJ) of the given instruction.
+    pub fn energy(&self, instruction: &Instruction, architecture: &Architecture) -> f64 {
+        activated_rows(instruction, architecture)
+            .map(|rows| self.e_act + (rows - 1) as f64 * self.e_act_per_row)
+            .sum::<f64>()
+            + self.e_pre
+    }
+
+    /// Returns the names of all parameters that are negative or not finite.
+    pub(super) fn invalid_parameters(&self) -> impl Iterator<Item = &'static str> {
+        [
+            ("t_ras", self.t_ras),
+            ("t_ras_per_row", self.t_ras_per_row),
+            ("t_rp", self.t_rp),
+            ("e_act", self.e_act),
+            ("e_act_per_row", self.e_act_per_row),
+            ("e_pre", self.e_pre),
+        ]
+        .into_iter()
+        .filter(|(_, value)| !value.is_finite() || *value < 0.0)
+        .map(|(name, _)| name)
+    }
+}
+
+/// Returns for each activation of the instruction the number of simultaneously activated rows.
+fn activated_rows<'a>(
+    instruction: &Instruction,
+    architecture: &'a Architecture,
+) -> impl Iterator<Item = usize> + 'a {
+    let (first, second) = match *instruction {
+        Instruction::AAP(from, to) => (from, Some(to)),
+        Instruction::AP(address) => (address, None),
+    };
+    std::iter::once(first)
+        .chain(second)
+        .map(move |address| address.row_addresses(architecture).count())
+}
+
+impl Program<'_> {
+    /// Returns the estimated latency (ns) of executing this program.
+    pub fn latency(&self) -> f64 {
+        let cost_model = &self.architecture.cost_model;
+        self.instructions
+            .iter()
+            .map(|instruction| cost_model.latency(instruction, self.architecture))
+            .sum()
+    }
+
+    /// Returns the estimated energy (nJ) of executing this program.
+    pub fn energy(&self) -> f64 {
+        let cost_model = &self.architecture.cost_model;
+        self.instructions
+            .iter()
+            .map(|instruction| cost_model.energy(instruction, self.architecture))
+            .sum()
+    }
+}
@@ -1,39 +1,107 @@
-use super::{compile, Architecture};
-use crate::opt_extractor::OptCostFunction;
-use eggmock::egg::{Analysis, EClass, Id, Language};
+use super::{compile, Architecture, ConstantAnalysis, Program};
+use crate::opt_extractor::{OptCostFunction, SyncCostFunction};
+use eggmock::egg::{EClass, Id, Language};
 use eggmock::{EggIdToSignal, Mig, MigLanguage, Network, NetworkLanguage, Signal};
-use either::Either;
-use rustc_hash::FxHashMap;
-use std::cell::RefCell;
-use std::cmp::{max, Ordering};
+use rustc_hash::{FxHashMap, FxHasher};
+use std::cmp::{max, max_by, Ordering};
+use std::hash::{Hash, Hasher};
 use std::iter;
-use std::ops::{Deref, Index};
-use std::rc::Rc;
+use std::ops::Index;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
 
+/// Costs each node by compiling the network extracted for it. Classes the analysis knows to be
+/// constant are only extracted as constants, which the compiler loads from the constant rows.
 pub struct CompilingCostFunction<'a> {
     pub architecture: &'a Architecture,
+    pub objective: ExtractionObjective,
+    pub cache: CompilationCache,
 }
 
+/// The program costs of compiled partial networks, which are determined by their root and the
+/// collapsed networks of their children. These are compared by structure, using the hash computed
+/// when collapsing them. The cache only references them weakly, so entries of networks that are no
+/// longer part of any cost are never hit again, but do not keep the networks alive.
+///
+/// The cache may be shared between threads. Threads looking up the same network at the same time
+/// may both compile it, so the number of hits depends on the scheduling, but the costs do not.
+#[derive(Default)]
+pub struct CompilationCache {
+    costs: Mutex<FxHashMap<CacheKey, Option<ProgramCost>>>,
+    hits: AtomicUsize,
+    lookups: AtomicUsize,
+}
+
+type CacheKey = (MigLanguage, Vec<GraphKey>);
+
+/// A collapsed partial network within a [CacheKey]. Keys are equal if they refer to the same
+/// network or to structurally equal networks that are both still alive.
+struct GraphKey {
+    hash: u64,
+    graph: Weak<CollapsedPartialGraph>,
+}
+
+/// The property of the compiled programs that is minimized during extraction.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+#[allow(dead_code)] // only constructed over FFI
+pub enum ExtractionObjective {
+    /// number of instructions
+    InstructionCount,
+    /// estimated latency, see [CostModel](super::CostModel)
+    Latency,
+    /// estimated energy, see [CostModel](super::CostModel)
+    Energy,
+    /// latency first, then energy and lastly the instruction count
+    Lexicographic,
+}
+
+/// How the network to compile is extracted from the e-graph.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+#[allow(dead_code)] // only constructed over FFI
+pub enum ExtractionMethod {
+    /// selects the cheapest node of each class bottom-up, see
+    /// [OptExtractor](crate::opt_extractor::OptExtractor)
+    Greedy,
+    /// selects the nodes of minimal total cost, where the cost of a node is that of compiling it on
+    /// its own, see [IlpExtractor](crate::ilp_extractor::IlpExtractor) and
+    /// [CompilingCostFunction::node_cost]. This per-node cost is a linear surrogate for the cost
+    /// of the compiled network, so the selection is optimal for the surrogate, not necessarily for
+    /// the compiled program.
+    Ilp,
+    /// selects the nodes of small total cost for all outputs at once within a bounded amount of
+    /// work, where the cost of a node is that of compiling it on its own, a surrogate for the cost
+    /// of the compiled network, see [DagExtractor](crate::dag_extractor::DagExtractor)
+    Dag,
+}
+
+/// Whether a partial network contains NOTs of NOTs, which are never preferred over other nodes.
 #[derive(Debug, Default, Eq, PartialEq)]
 pub enum NotNesting {
+    /// neither the root is a NOT nor the network contains nested NOTs
     #[default]
     NotANot,
+    /// the root is a NOT, but the network contains no nested NOTs
     FirstNot,
+    /// the network contains nested NOTs somewhere
     NestedNots,
 }
 
 #[derive(Debug)]
 pub struct StackedPartialGraph {
-    nodes: Vec<Rc<FxHashMap<Id, MigLanguage>>>,
+    nodes: Vec<Arc<FxHashMap<Id, MigLanguage>>>,
     first_free_id: usize,
     root: MigLanguage,
 }
 
 #[derive(Debug)]
 pub struct CollapsedPartialGraph {
-    nodes: Rc<FxHashMap<Id, MigLanguage>>,
+    nodes: Arc<FxHashMap<Id, MigLanguage>>,
     first_free_id: usize,
     root_id: Id,
+    /// hash of the nodes and the root, see [CollapsedPartialGraph::structural_hash]
+    hash: u64,
 }
 
 impl StackedPartialGraph {
@@ -46,7 +114,7 @@ impl StackedPartialGraph {
     }
     pub fn new(
         root: MigLanguage,
-        child_graphs: impl IntoIterator<Item = Rc<CollapsedPartialGraph>>,
+        child_graphs: impl IntoIterator<Item = Arc<CollapsedPartialGraph>>,
     ) -> Self {
         let mut nodes = Vec::new();
         let mut first_free_id = 0;
@@ -70,35 +138,108 @@ impl StackedPartialGraph {
         );
         nodes.insert(real_id, self.root.clone());
         CollapsedPartialGraph {
-            nodes: Rc::new(nodes),
+            hash: CollapsedPartialGraph::structural_hash(&nodes, real_id),
+            nodes: Arc::new(nodes),
             first_free_id,
             root_id: real_id,
         }
     }
 }
 
+impl CollapsedPartialGraph {
+    /// Returns a hash of the given nodes and root that does not depend on the iteration order of
+    /// the nodes.
+    fn structural_hash(nodes: &FxHashMap<Id, MigLanguage>, root_id: Id) -> u64 {
+        let hash = |value: &dyn Fn(&mut FxHasher)| {
+            let mut hasher = FxHasher::default();
+            value(&mut hasher);
+            hasher.finish()
+        };
+        let nodes = nodes
+            .iter()
+            .map(|entry| hash(&|hasher| entry.hash(hasher)))
+            .fold(0u64, u64::wrapping_add);
+        hash(&|hasher| (nodes, root_id).hash(hasher))
+    }
+
+    fn same_structure(&self, other: &Self) -> bool {
+        self.hash == other.hash
+            && self.root_id == other.root_id
+            && self.first_free_id == other.first_free_id
+            && (Arc::ptr_eq(&self.nodes, &other.nodes) || self.nodes == other.nodes)
+    }
+}
+
+/// The cost of extracting a partial network, which is totally ordered and monotone, i.e. never less
+/// than the costs of the children it was created from. Costs with nested NOTs are greater than all
+/// other costs and otherwise the [ProgramCost]s are compared.
+///
+/// Costs can be shared between threads. The partial network is collapsed at most once, when it
+/// becomes the child of another one, and is immutable afterwards.
 #[derive(Debug)]
 pub struct CompilingCost {
-    partial: RefCell<Either<StackedPartialGraph, Rc<CollapsedPartialGraph>>>,
+    partial: StackedPartialGraph,
+    collapsed: OnceLock<Arc<CollapsedPartialGraph>>,
     not_nesting: NotNesting,
-    program_cost: usize,
+    program_cost: ProgramCost,
 }
 
-impl<A: Analysis<MigLanguage>> OptCostFunction<MigLanguage, A> for CompilingCostFunction<'_> {
-    type Cost = Rc<CompilingCost>;
+/// The cost of a compiled program as determined by the [ExtractionObjective], compared
+/// lexicographically with [f64::total_cmp]. Unused components are zero.
+type ProgramCost = (f64, f64, f64);
+
+impl<A: ConstantAnalysis> OptCostFunction<MigLanguage, A> for CompilingCostFunction<'_> {
+    type Cost = Arc<CompilingCost>;
 
     fn cost<C>(
         &mut self,
         eclass: &EClass<MigLanguage, A::Data>,
         enode: &MigLanguage,
-        mut costs: C,
+        costs: C,
+    ) -> Option<Self::Cost>
+    where
+        C: FnMut(Id) -> Self::Cost,
+    {
+        self.class_cost::<A, _>(eclass, enode, costs)
+    }
+}
+
+impl<A: ConstantAnalysis> SyncCostFunction<MigLanguage, A> for CompilingCostFunction<'_> {
+    fn sync_cost<C>(
+        &self,
+        eclass: &EClass<MigLanguage, A::Data>,
+        enode: &MigLanguage,
+        costs: C,
     ) -> Option<Self::Cost>
     where
         C: FnMut(Id) -> Self::Cost,
+    {
+        self.class_cost::<A, _>(eclass, enode, costs)
+    }
+}
+
+impl CompilingCostFunction<'_> {
+    /// Returns the cost of the given node of the given class, where `costs` returns the costs of
+    /// its children.
+    fn class_cost<A, C>(
+        &self,
+        class: &EClass<MigLanguage, A::Data>,
+        enode: &MigLanguage,
+        mut costs: C,
+    ) -> Option<Arc<CompilingCost>>
+    where
+        A: ConstantAnalysis,
+        C: FnMut(Id) -> Arc<CompilingCost>,
     {
         // detect self-cycles, other cycles will be detected by compiling, which will result in an
         // error
-        if enode.children().contains(&eclass.id) {
+        if enode.children().contains(&class.id) {
+            return None;
+        }
+        // the class also contains the constant or its negation, whose children are constant too
+        if A::constant(&class.data).is_some()
+            && !matches!(enode, MigLanguage::False | MigLanguage::Not(_))
+        {
             return None;
         }
         let root = enode.clone();
@@ -114,6 +255,8 @@ impl<A: Analysis<MigLanguage>> OptCostFunction<MigLanguage, A> for CompilingCost
                 };
                 CompilingCost::with_children(
                     self.architecture,
+                    self.objective,
+                    &self.cache,
                     root,
                     iter::once((*id, cost)),
                     nesting,
@@ -121,56 +264,104 @@ impl<A: Analysis<MigLanguage>> OptCostFunction<MigLanguage, A> for CompilingCost
             }
             MigLanguage::Maj(children) => CompilingCost::with_children(
                 self.architecture,
+                self.objective,
+                &self.cache,
                 root,
                 children.map(|id| (id, costs(id))),
                 NotNesting::NotANot,
             )?,
         };
-        Some(Rc::new(cost))
+        Some(Arc::new(cost))
+    }
+
+    /// Returns the cost of compiling the given node on its own, with distinct inputs as children,
+    /// or [None] if it cannot be compiled. This is the first component of the [ProgramCost], i.e.
+    /// the latency for [ExtractionObjective::Lexicographic]. The inputs are created anew on each
+    /// call, so the [CompilationCache] is not used.
+    pub fn node_cost(&self, node: &MigLanguage) -> Option<f64> {
+        let nesting = match node {
+            MigLanguage::False | MigLanguage::Input(_) => return Some(0.0),
+            MigLanguage::Not(_) => NotNesting::FirstNot,
+            MigLanguage::Maj(_) => NotNesting::NotANot,
+        };
+        let children = node.children().iter().enumerate().map(|(i, id)| {
+            let input = CompilingCost::leaf(MigLanguage::Input(i as u64));
+            (*id, Arc::new(input))
+        });
+        let cost = CompilingCost::with_children(
+            self.architecture,
+            self.objective,
+            &CompilationCache::default(),
+            node.clone(),
+            children,
+            nesting,
+        )?;
+        Some(cost.program_cost.0)
     }
 }
 
 impl CompilingCost {
     pub fn leaf(root: MigLanguage) -> Self {
         Self {
-            partial: RefCell::new(Either::Left(StackedPartialGraph::leaf(root))),
+            partial: StackedPartialGraph::leaf(root),
+            collapsed: OnceLock::new(),
             not_nesting: NotNesting::NotANot,
-            program_cost: 0,
+            program_cost: (0.0, 0.0, 0.0),
         }
     }
     pub fn with_children(
         architecture: &Architecture,
+        objective: ExtractionObjective,
+        cache: &CompilationCache,
         root: MigLanguage,
-        child_costs: impl IntoIterator<Item = (Id, Rc<CompilingCost>)>,
+        child_costs: impl IntoIterator<Item = (Id, Arc<CompilingCost>)>,
         not_nesting: NotNesting,
     ) -> Option<Self> {
-        let child_graphs = child_costs
-            .into_iter()
-            .map(|(id, cost)| cost.collapsed_graph(id));
-        let partial_graph = StackedPartialGraph::new(root, child_graphs);
-        let program_cost = match compile(architecture, &partial_graph.with_backward_edges()) {
-            Err(_) => return None,
-            Ok(program) => program.instructions.len(),
-        };
+        let mut not_nesting = not_nesting;
+        let mut children_cost = (0.0, 0.0, 0.0);
+        let child_graphs = child_costs.into_iter().map(|(id, cost)| {
+            if cost.not_nesting == NotNesting::NestedNots {
+                not_nesting = NotNesting::NestedNots;
+            }
+            children_cost = max_by(children_cost, cost.program_cost, cmp_program_cost);
+            cost.collapsed_graph(id)
+        });
+        let child_graphs: Vec<_> = child_graphs.collect();
+        let key = child_graphs.iter().map(GraphKey::new).collect();
+        let partial_graph = StackedPartialGraph::new(root.clone(), child_graphs);
+        let compiled_cost = cache.get_or_insert_with((root, key), || {
+            let program = compile(architecture, &partial_graph.with_backward_edges());
+            program.ok().map(|program| objective.program_cost(&program))
+        });
+        // compiling a larger network may yield a cheaper program, which would break the
+        // monotonicity
+        let program_cost = max_by(compiled_cost?, children_cost, cmp_program_cost);
         Self {
-            partial: RefCell::new(Either::Left(partial_graph)),
+            partial: partial_graph,
+            collapsed: OnceLock::new(),
             not_nesting,
             program_cost,
         }
         .into()
     }
-    pub fn collapsed_graph(&self, id: Id) -> Rc<CollapsedPartialGraph> {
-        let mut partial = self.partial.borrow_mut();
-        let stacked = match partial.deref() {
-            Either::Left(stacked) => stacked,
-            Either::Right(collapsed) => {
-                assert_eq!(collapsed.root_id, id);
-                return collapsed.clone();
-            }
-        };
-        let collapsed = Rc::new(stacked.collapse(id));
-        *partial = Either::Right(collapsed.clone());
-        collapsed
+    pub fn collapsed_graph(&self, id: Id) -> Arc<CollapsedPartialGraph> {
+        let collapsed = self
+            .collapsed
+            .get_or_init(|| Arc::new(self.partial.collapse(id)));
+        assert_eq!(collapsed.root_id, id);
+        collapsed.clone()
+    }
+}
+
+impl ExtractionObjective {
+    fn program_cost(self, program: &Program) -> ProgramCost {
+        let instructions = program.instructions.len() as f64;
+        match self {
+            Self::InstructionCount => (instructions, 0.0, 0.0),
+            Self::Latency => (program.latency(), 0.0, 0.0),
+            Self::Energy => (program.energy(), 0.0, 0.0),
+            Self::Lexicographic => (program.latency(), program.energy(), instructions),
+        }
     }
 }
 
@@ -210,31 +401,243 @@ impl Network for StackedPartialGraph {
     }
 }
 
-impl PartialEq for CompilingCost {
-    fn eq(&self, other: &Self) -> bool {
-        if other.not_nesting == NotNesting::NestedNots && self.not_nesting == NotNesting::NestedNots
-        {
-            true
-        } else {
-            self.program_cost.eq(&other.program_cost)
+impl CompilationCache {
+    /// Returns the cached cost for the given key or computes and caches it with `compute`, which
+    /// is called without holding the lock.
+    fn get_or_insert_with(
+        &self,
+        key: CacheKey,
+        compute: impl FnOnce() -> Option<ProgramCost>,
+    ) -> Option<ProgramCost> {
+        self.lookups.fetch_add(1, AtomicOrdering::Relaxed);
+        if let Some(cost) = self.costs.lock().unwrap().get(&key) {
+            self.hits.fetch_add(1, AtomicOrdering::Relaxed);
+            return *cost;
+        }
+        let cost = compute();
+        self.costs.lock().unwrap().insert(key, cost);
+        cost
+    }
+
+    pub fn hits(&self) -> usize {
+        self.hits.load(AtomicOrdering::Relaxed)
+    }
+
+    pub fn lookups(&self) -> usize {
+        self.lookups.load(AtomicOrdering::Relaxed)
+    }
+}
+
+impl GraphKey {
+    fn new(graph: &Arc<CollapsedPartialGraph>) -> Self {
+        Self {
+            hash: graph.hash,
+            graph: Arc::downgrade(graph),
         }
     }
 }
 
+impl PartialEq for GraphKey {
+    fn eq(&self, other: &Self) -> bool {
+        // the weak references keep the allocations, so the addresses of dropped networks are not
+        // reused
+        self.hash == other.hash
+            && (Weak::ptr_eq(&self.graph, &other.graph)
+                || match (self.graph.upgrade(), other.graph.upgrade()) {
+                    (Some(graph), Some(other)) => graph.same_structure(&other),
+                    _ => false,
+                })
+    }
+}
+
+impl Eq for GraphKey {}
+
+impl Hash for GraphKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.hash.hash(state)
+    }
+}
+
+fn cmp_program_cost(a: &ProgramCost, b: &ProgramCost) -> Ordering {
+    a.0.total_cmp(&b.0)
+        .then(a.1.total_cmp(&b.1))
+        .then(a.2.total_cmp(&b.2))
+}
+
+impl Ord for CompilingCost {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let self_nested = self.not_nesting == NotNesting::NestedNots;
+        let other_nested = other.not_nesting == NotNesting::NestedNots;
+        self_nested
+            .cmp(&other_nested)
+            .then_with(|| cmp_program_cost(&self.program_cost, &other.program_cost))
+    }
+}
+
 impl PartialOrd for CompilingCost {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        #[allow(clippy::collapsible_else_if)]
-        if self.not_nesting == NotNesting::NestedNots {
-            if other.not_nesting == NotNesting::NestedNots {
-                Some(Ordering::Equal)
-            } else {
-                Some(Ordering::Greater)
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for CompilingCost {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for CompilingCost {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambit::presets::AMBIT;
+    use crate::opt_extractor::OptExtractor;
+    use eggmock::egg::EGraph;
+
+    /// Returns a deterministic sequence of pseudo-random numbers (xorshift).
+    fn random(seed: u64) -> impl FnMut(usize) -> usize {
+        let mut state = seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1;
+        move |bound| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % bound as u64) as usize
+        }
+    }
+
+    #[test]
+    fn cost_is_total_order() {
+        let components = [0.0, -0.0, 1.0, 2.5, f64::INFINITY, f64::NAN];
+        let mut next = random(1);
+        let costs: Vec<CompilingCost> = (0..40)
+            .map(|_| {
+                let mut cost = CompilingCost::leaf(MigLanguage::False);
+                cost.program_cost = (
+                    components[next(components.len())],
+                    components[next(components.len())],
+                    components[next(components.len())],
+                );
+                cost.not_nesting = match next(3) {
+                    0 => NotNesting::NotANot,
+                    1 => NotNesting::FirstNot,
+                    _ => NotNesting::NestedNots,
+                };
+                cost
+            })
+            .collect();
+        for a in &costs {
+            assert_eq!(a.cmp(a), Ordering::Equal);
+            for b in &costs {
+                assert_eq!(a.cmp(b), b.cmp(a).reverse(), "{a:?} and {b:?}");
+                assert_eq!(a == b, a.cmp(b) == Ordering::Equal);
+                assert_eq!(a.partial_cmp(b), Some(a.cmp(b)));
+                for c in &costs {
+                    if a <= b && b <= c {
+                        assert!(a <= c, "{a:?}, {b:?} and {c:?}");
+                    }
+                }
             }
-        } else {
-            if other.not_nesting == NotNesting::NestedNots {
-                Some(Ordering::Less)
-            } else {
-                self.program_cost.partial_cmp(&other.program_cost)
+        }
+    }
+
+    #[test]
+    fn cache_keys_compare_structure() {
+        let collapse = |input| Arc::new(StackedPartialGraph::leaf(input).collapse(Id::from(1)));
+        let graph = collapse(MigLanguage::Input(0));
+        let equal = collapse(MigLanguage::Input(0));
+        let other = collapse(MigLanguage::Input(1));
+        assert!(GraphKey::new(&graph) == GraphKey::new(&equal));
+        assert!(GraphKey::new(&graph) != GraphKey::new(&other));
+
+        let cache = CompilationCache::default();
+        fn key(graph: &Arc<CollapsedPartialGraph>) -> CacheKey {
+            (MigLanguage::Not(Id::from(1)), vec![GraphKey::new(graph)])
+        }
+        assert_eq!(
+            cache.get_or_insert_with(key(&graph), || Some((1.0, 0.0, 0.0))),
+            Some((1.0, 0.0, 0.0))
+        );
+        assert_eq!(
+            cache.get_or_insert_with(key(&equal), || None),
+            Some((1.0, 0.0, 0.0))
+        );
+        assert_eq!(cache.hits(), 1);
+
+        // dropped networks are not kept alive by the cache and no longer match
+        drop(graph);
+        let stale = key(&equal);
+        drop(equal);
+        assert!(stale.1[0].graph.upgrade().is_none());
+        let again = collapse(MigLanguage::Input(0));
+        assert_eq!(cache.get_or_insert_with(key(&again), || None), None);
+    }
+
+    /// Returns an e-graph of a random MIG with the given number of inputs and MAJ nodes, where
+    /// some classes contain additional nodes with permuted children or doubly negated nodes.
+    fn random_egraph(seed: u64, inputs: u64, majs: usize) -> EGraph<MigLanguage, ()> {
+        let mut next = random(seed);
+        let mut egraph = EGraph::default();
+        let mut classes: Vec<Id> = (0..inputs)
+            .map(|i| egraph.add(MigLanguage::Input(i)))
+            .collect();
+        classes.push(egraph.add(MigLanguage::False));
+        for _ in 0..majs {
+            let mut children = [0; 3].map(|_| classes[next(classes.len())]);
+            for child in &mut children {
+                if next(3) == 0 {
+                    *child = egraph.add(MigLanguage::Not(*child));
+                }
+            }
+            let id = egraph.add(MigLanguage::Maj(children));
+            match next(4) {
+                0 => {
+                    let [a, b, c] = children;
+                    let permuted = egraph.add(MigLanguage::Maj([c, a, b]));
+                    egraph.union(id, permuted);
+                }
+                1 => {
+                    let not = egraph.add(MigLanguage::Not(id));
+                    let not_not = egraph.add(MigLanguage::Not(not));
+                    egraph.union(id, not_not);
+                }
+                _ => {}
+            }
+            classes.push(id);
+        }
+        egraph.rebuild();
+        egraph
+    }
+
+    #[test]
+    fn cost_is_monotone() {
+        for seed in 0..16 {
+            let egraph = random_egraph(seed, 4, 24);
+            let extractor = OptExtractor::new(
+                &egraph,
+                CompilingCostFunction {
+                    architecture: &AMBIT,
+                    objective: ExtractionObjective::Lexicographic,
+                    cache: CompilationCache::default(),
+                },
+            );
+            for class in egraph.classes() {
+                let (Some(node), Some(cost)) = (
+                    extractor.find_best_node(class.id),
+                    extractor.find_best_cost(class.id),
+                ) else {
+                    continue;
+                };
+                for child in node.children() {
+                    let child_cost = extractor
+                        .find_best_cost(*child)
+                        .expect("children of extracted nodes should be extracted");
+                    assert!(
+                        cost >= child_cost,
+                        "class {} is cheaper than its child {child} (seed {seed})",
+                        class.id
+                    );
+                }
             }
         }
     }
@@ -1,200 +1,424 @@
+mod allocation;
+mod analysis;
+mod architecture;
+mod assembly;
 mod compilation;
+mod cost_model;
 mod extraction;
 mod optimization;
+mod presets;
 mod program;
+mod program_ffi;
 mod rows;
+mod rules;
+mod simulation;
+mod trace;
+mod verification;
 
-use std::sync::LazyLock;
-use std::time::Instant;
+use std::cell::Cell;
+use std::ffi::{c_char, CStr};
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::PathBuf;
+use std::ptr;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
 
-use self::compilation::compile;
-use self::extraction::CompilingCostFunction;
+use self::compilation::{compile, CompileError};
+use self::extraction::{
+    CompilationCache, CompilingCostFunction, ExtractionMethod, ExtractionObjective,
+};
 
+use crate::dag_extractor::DagExtractor;
+use crate::ilp_extractor::IlpExtractor;
 use crate::opt_extractor::{OptExtractionNetwork, OptExtractor};
-use eggmock::egg::{rewrite, EGraph, Rewrite, Runner};
+use allocation::*;
+use analysis::*;
+use architecture::*;
+use cost_model::*;
+use eggmock::egg::{
+    Analysis, BackoffScheduler, EGraph, Id, Language, Runner, SimpleScheduler, StopReason,
+};
 use eggmock::{Mig, MigLanguage, MigReceiverFFI, Network, Receiver, ReceiverFFI};
+use either::Either;
+use presets::*;
 use program::*;
+use program_ffi::ProgramFFI;
+use rayon::{ThreadPool, ThreadPoolBuilder};
 use rows::*;
+use rules::*;
+use rustc_hash::FxHashMap;
+use simulation::*;
+use trace::TraceSettings;
+use verification::{verify, verify_program, VerificationError};
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
-pub enum BitwiseOperand {
-    T(u8),
-    DCC { inverted: bool, index: u8 },
-}
+struct CompilingReceiverResult<'a, A: ConstantAnalysis + 'a> {
+    output: CompilerOutput<'a, A>,
 
-#[derive(Clone, Debug)]
-pub struct Architecture {
-    maj_ops: Vec<usize>,
-    multi_activations: Vec<Vec<BitwiseOperand>>,
-    num_dcc: u8,
+    t_runner: u128,
+    t_extractor: u128,
+    t_compiler: u128,
+    verification_failed: bool,
+    /// the reason why the extracted network could not be compiled, in which case the program is
+    /// empty
+    compile_error: Option<CompileError>,
+    /// the reason why the rules could not be set up, in which case neither rewriting nor
+    /// compilation took place
+    rules_error: Option<RuleSetError>,
+    /// the reason why the rows of the compiled program could not be allocated if allocation is
+    /// enabled in the settings
+    allocation_error: Option<AllocationError>,
+    /// number of physical rows used for spilling if the rows were allocated
+    spill_rows: u32,
+    /// the reason why the equality saturation of the kept round stopped and after how many
+    /// iterations
+    stop_reason: RunnerStopReason,
+    iterations: usize,
+    /// number of e-classes merged by the functional reduction
+    functional_merges: usize,
+    /// the instruction count of the program compiled in each round
+    round_instructions: Vec<usize>,
+    /// number of partial networks whose cost was taken from the [CompilationCache] and number of
+    /// lookups in total
+    cache_hits: usize,
+    cache_lookups: usize,
 }
 
-impl Architecture {
-    pub fn new(multi_activations: Vec<Vec<BitwiseOperand>>, num_dcc: u8) -> Self {
-        let maj_ops = multi_activations
-            .iter()
-            .enumerate()
-            .filter(|(_, ops)| ops.len() == 3)
-            .map(|(i, _)| i)
-            .collect();
-        Self {
-            maj_ops,
-            multi_activations,
-            num_dcc,
-        }
-    }
+#[ouroboros::self_referencing]
+struct CompilerOutput<'a, A: ConstantAnalysis + 'a> {
+    graph: EGraph<MigLanguage, A>,
+    #[borrows(graph)]
+    #[covariant]
+    ntk: OptExtractionNetwork<
+        Either<
+            OptExtractor<'this, CompilingCostFunction<'a>, MigLanguage, A>,
+            Either<IlpExtractor<'this, MigLanguage, A>, DagExtractor<'this, MigLanguage, A>>,
+        >,
+    >,
+    #[borrows(ntk)]
+    program: Program<'a>,
 }
 
-static ARCHITECTURE: LazyLock<Architecture> = LazyLock::new(|| {
-    use BitwiseOperand::*;
-    Architecture::new(
-        vec![
-            // 2 rows
-            vec![
-                DCC {
-                    index: 0,
-                    inverted: true,
-                },
-                T(0),
-            ],
-            vec![
-                DCC {
-                    inverted: true,
-                    index: 1,
-                },
-                T(1),
-            ],
-            vec![T(2), T(3)],
-            vec![T(0), T(3)],
-            // 3 rows
-            vec![T(0), T(1), T(2)],
-            vec![T(1), T(2), T(3)],
-            vec![
-                DCC {
-                    index: 0,
-                    inverted: false,
-                },
-                T(1),
-                T(2),
-            ],
-            vec![
-                DCC {
-                    index: 1,
-                    inverted: false,
-                },
-                T(0),
-                T(3),
-            ],
-        ],
-        2,
-    )
-});
+/// Merges e-classes of the e-graph proven to be equivalent and returns the number of merges.
+type Reduction<A> = fn(&mut EGraph<MigLanguage, A>) -> usize;
 
-static REWRITE_RULES: LazyLock<Vec<Rewrite<MigLanguage, ()>>> = LazyLock::new(|| {
-    let mut rules = vec![
-        rewrite!("commute_1"; "(maj ?a ?b ?c)" => "(maj ?b ?a ?c)"),
-        rewrite!("commute_2"; "(maj ?a ?b ?c)" => "(maj ?a ?c ?b)"),
-        rewrite!("not_not"; "(! (! ?a))" => "?a"),
-        rewrite!("maj_1"; "(maj ?a ?a ?b)" => "?a"),
-        rewrite!("maj_2"; "(maj ?a (! ?a) ?b)" => "?b"),
-        rewrite!("associativity"; "(maj ?a ?b (maj ?c ?b ?d))" => "(maj ?d ?b (maj ?c ?b ?a))"),
-    ];
-    rules.extend(rewrite!("invert"; "(! (maj ?a ?b ?c))" <=> "(maj (! ?a) (! ?b) (! ?c))"));
-    rules.extend(rewrite!("distributivity"; "(maj ?a ?b (maj ?c ?d ?e))" <=> "(maj (maj ?a ?b ?c) (maj ?a ?b ?d) ?e)"));
-    rules
-});
+/// Returns a receiver that rewrites the received network in an e-graph with the given analysis,
+/// extracts the network to compile and compiles it. If given, `reduce` is called before each
+/// iteration of the equality saturation.
+///
+/// With more than one round in the settings, the extracted network is rewritten, extracted and
+/// compiled again in a fresh e-graph as long as the instruction count improves, and the best
+/// program is kept. If rows are allocated, the trace of the allocated program is written to the
+/// given files.
+fn compiling_receiver<'a, A>(
+    architecture: &'a Architecture,
+    analysis: A,
+    rules: Result<RuleSet<A>, RuleSetError>,
+    reduce: Option<Reduction<A>>,
+    settings: CompilerSettings,
+    trace_files: TraceFiles,
+) -> impl Receiver<Result = CompilingReceiverResult<'a, A>, Node = Mig> + 'a
+where
+    A: ConstantAnalysis + Clone + Default + Sync + 'static,
+    A::Data: Clone + Sync,
+{
+    EGraph::<MigLanguage, ()>::new(()).map(move |(received, outputs)| {
+        let (rules, rules_error) = match rules {
+            Ok(rules) => (Some(rules), None),
+            Err(err) => (None, Some(err)),
+        };
+        let (graph, graph_outputs) = with_analysis(&received, &outputs, analysis.clone());
+        // without an analysis, the e-graph contains exactly the received network, so merges by
+        // the analysis or the reduction are verified as well
+        let input = settings.verify.then_some(received);
+        let mut passes = CompilationPasses {
+            architecture,
+            settings,
+            rules: rules.filter(|_| settings.rewrite),
+            reduce,
+            t_runner: 0,
+            t_extractor: 0,
+            t_compiler: 0,
+            functional_merges: Rc::new(Cell::new(0)),
+            cache_hits: 0,
+            cache_lookups: 0,
+            thread_pool: settings.thread_pool(),
+        };
 
-impl BitwiseOperand {
-    pub fn row(&self) -> BitwiseRow {
-        match self {
-            BitwiseOperand::T(t) => BitwiseRow::T(*t),
-            BitwiseOperand::DCC { index, .. } => BitwiseRow::DCC(*index),
+        let mut best = passes.run(graph, graph_outputs, rules_error.is_some());
+        let mut round_instructions = vec![best.output.borrow_program().instructions.len()];
+        let max_rounds = (settings.rounds as usize).clamp(1, MAX_ROUNDS);
+        while passes.rules.is_some()
+            && best.compile_error.is_none()
+            && round_instructions.len() < max_rounds
+        {
+            let (graph, next_outputs) = best
+                .output
+                .borrow_ntk()
+                .send(EGraph::<MigLanguage, _>::new(analysis.clone()));
+            let next = passes.run(graph, next_outputs, false);
+            if next.compile_error.is_some() {
+                break;
+            }
+            let instructions = next.output.borrow_program().instructions.len();
+            round_instructions.push(instructions);
+            if settings.verbose {
+                println!(
+                    "== Round {}: {instructions} instructions",
+                    round_instructions.len()
+                );
+            }
+            if instructions >= best.output.borrow_program().instructions.len() {
+                break;
+            }
+            best = next;
         }
+        let Pass {
+            output,
+            compile_error,
+            stop_reason,
+            iterations,
+        } = best;
+
+        let mut verification_failed = false;
+        let mut allocation_error = None;
+        let mut spill_rows = 0;
+        if rules_error.is_none() && compile_error.is_none() {
+            let program = output.borrow_program();
+            if settings.print_program || settings.verbose {
+                if settings.verbose {
+                    println!("== Program")
+                }
+                println!("{program}");
+            }
+            if let Some(input) = &input {
+                if let Err(err) = verify(input, &outputs, output.borrow_ntk(), program) {
+                    eprintln!("{err}");
+                    verification_failed = true;
+                }
+            }
+            if let Some(subarray) = settings.subarray(architecture) {
+                match program.allocate_rows(&subarray) {
+                    Ok(physical) => {
+                        if settings.verbose {
+                            println!("== Physical Program");
+                            println!("inputs: {:?}", physical.inputs);
+                            println!("outputs: {:?}", physical.outputs);
+                            println!("{physical}");
+                        }
+                        spill_rows = physical.spill_rows;
+                        if let Err(err) = trace_files.write(&physical) {
+                            eprintln!("could not write the trace: {err}");
+                        }
+                    }
+                    Err(err) => allocation_error = Some(err),
+                }
+            }
+        }
+        if settings.verbose {
+            println!("== Timings");
+            println!("t_runner: {}ms", passes.t_runner);
+            println!("t_extractor: {}ms", passes.t_extractor);
+            println!("t_compiler: {}ms", passes.t_compiler);
+            println!(
+                "compilation cache: {} hits in {} lookups",
+                passes.cache_hits, passes.cache_lookups
+            );
+        }
+        CompilingReceiverResult {
+            output,
+            t_runner: passes.t_runner,
+            t_extractor: passes.t_extractor,
+            t_compiler: passes.t_compiler,
+            verification_failed,
+            compile_error,
+            rules_error,
+            allocation_error,
+            spill_rows,
+            stop_reason,
+            iterations,
+            functional_merges: passes.functional_merges.get(),
+            round_instructions,
+            cache_hits: passes.cache_hits,
+            cache_lookups: passes.cache_lookups,
+        }
+    })
+}
+
+/// Adds the network received into an e-graph without an analysis, i.e. with exactly one node per
+/// class, to a new e-graph with the given analysis. Returns the new e-graph and the classes of the
+/// given outputs in it.
+fn with_analysis<A: Analysis<MigLanguage>>(
+    received: &EGraph<MigLanguage, ()>,
+    outputs: &[Id],
+    analysis: A,
+) -> (EGraph<MigLanguage, A>, Vec<Id>) {
+    // classes are created after those of their children, so adding them by id adds the children
+    // of each node first
+    let mut classes: Vec<_> = received.classes().collect();
+    classes.sort_unstable_by_key(|class| class.id);
+    let mut graph = EGraph::new(analysis);
+    let mut ids = FxHashMap::default();
+    for class in classes {
+        let node = class.nodes[0]
+            .clone()
+            .map_children(|child| ids[&received.find(child)]);
+        ids.insert(class.id, graph.add(node));
     }
-    pub fn is_dcc(&self) -> bool {
-        matches!(self, BitwiseOperand::DCC { .. })
-    }
-    pub fn inverted(&self) -> bool {
-        matches!(self, BitwiseOperand::DCC { inverted: true, .. })
-    }
+    graph.rebuild();
+    let outputs = outputs
+        .iter()
+        .map(|output| graph.find(ids[&received.find(*output)]))
+        .collect();
+    (graph, outputs)
 }
 
-struct CompilingReceiverResult<'a> {
-    output: CompilerOutput<'a>,
+/// The result of a single [CompilationPasses::run].
+struct Pass<'a, A: ConstantAnalysis + 'a> {
+    output: CompilerOutput<'a, A>,
+    compile_error: Option<CompileError>,
+    /// the reason why the equality saturation stopped and after how many iterations
+    stop_reason: RunnerStopReason,
+    iterations: usize,
+}
 
+/// Rewrites, extracts and compiles networks, summing up the statistics of all passes.
+struct CompilationPasses<'a, A: ConstantAnalysis> {
+    architecture: &'a Architecture,
+    settings: CompilerSettings,
+    /// the rules to rewrite with, or none if rewriting is disabled
+    rules: Option<RuleSet<A>>,
+    reduce: Option<Reduction<A>>,
     t_runner: u128,
     t_extractor: u128,
     t_compiler: u128,
+    functional_merges: Rc<Cell<usize>>,
+    cache_hits: usize,
+    cache_lookups: usize,
+    /// the threads of the greedy extraction, or none if it is sequential
+    thread_pool: Option<ThreadPool>,
 }
 
-#[ouroboros::self_referencing]
-struct CompilerOutput<'a> {
-    graph: EGraph<MigLanguage, ()>,
-    #[borrows(graph)]
-    #[covariant]
-    ntk: OptExtractionNetwork<OptExtractor<'this, CompilingCostFunction<'a>, MigLanguage, ()>>,
-    #[borrows(ntk)]
-    program: Program<'a>,
-}
-
-fn compiling_receiver<'a>(
-    architecture: &'a Architecture,
-    rules: &'a [Rewrite<MigLanguage, ()>],
-    settings: CompilerSettings,
-) -> impl Receiver<Result = CompilingReceiverResult<'a>, Node = Mig> + 'a {
-    EGraph::<MigLanguage, _>::new(()).map(move |(mut graph, outputs)| {
-        let t_runner = if settings.rewrite {
-            let t_runner = std::time::Instant::now();
-            let runner = Runner::default().with_egraph(graph).run(rules);
-            let t_runner = t_runner.elapsed().as_millis();
-            if settings.verbose {
+impl<'a, A> CompilationPasses<'a, A>
+where
+    A: ConstantAnalysis + Default + Sync + 'static,
+    A::Data: Sync,
+{
+    /// Rewrites the given e-graph, extracts the network with the given outputs from it and
+    /// compiles it unless `skip_compilation` is set. The program is empty if it was not compiled.
+    fn run(
+        &mut self,
+        mut graph: EGraph<MigLanguage, A>,
+        outputs: Vec<Id>,
+        skip_compilation: bool,
+    ) -> Pass<'a, A> {
+        let mut stop_reason = RunnerStopReason::NotRun;
+        let mut iterations = 0;
+        if let Some(rules) = &self.rules {
+            let t_runner = Instant::now();
+            let mut runner = self.settings.runner();
+            if let Some(reduce) = self.reduce {
+                let functional_merges = self.functional_merges.clone();
+                runner = runner.with_hook(move |runner| {
+                    functional_merges.set(functional_merges.get() + reduce(&mut runner.egraph));
+                    Ok(())
+                });
+            }
+            let runner = runner.with_egraph(graph).run(rules.rules());
+            self.t_runner += t_runner.elapsed().as_millis();
+            if self.settings.verbose {
                 println!("== Runner Report");
                 runner.print_report();
             }
+            stop_reason = runner.stop_reason.as_ref().into();
+            iterations = runner.iterations.len();
             graph = runner.egraph;
-            t_runner
-        } else {
-            0
-        };
+        }
 
+        let architecture = self.architecture;
+        let objective = self.settings.objective;
+        let extraction = self.settings.extraction;
+        let thread_pool = self.thread_pool.as_ref();
         let mut t_extractor = 0;
         let mut t_compiler = 0;
-
+        let mut cache = (0, 0);
+        let mut compile_error = None;
         let output = CompilerOutput::new(
             graph,
             |graph| {
                 let start_time = Instant::now();
-                let extractor = OptExtractor::new(graph, CompilingCostFunction { architecture });
+                let cost_function = CompilingCostFunction {
+                    architecture,
+                    objective,
+                    cache: CompilationCache::default(),
+                };
+                let extractor = match extraction {
+                    ExtractionMethod::Greedy => None,
+                    ExtractionMethod::Ilp if graph.total_number_of_nodes() > MAX_ILP_NODES => {
+                        eprintln!(
+                            "the e-graph is too large for ILP extraction, extracting greedily"
+                        );
+                        None
+                    }
+                    ExtractionMethod::Ilp => {
+                        IlpExtractor::new(graph, &outputs, |node| cost_function.node_cost(node))
+                            .map(Either::Left)
+                            .inspect_err(|err| {
+                                eprintln!("ILP extraction failed ({err}), extracting greedily")
+                            })
+                            .ok()
+                    }
+                    ExtractionMethod::Dag => {
+                        Some(Either::Right(DagExtractor::new(graph, &outputs, |node| {
+                            cost_function.node_cost(node)
+                        })))
+                    }
+                };
+                let extractor = match extractor {
+                    Some(extractor) => Either::Right(extractor),
+                    None => {
+                        let extractor = match thread_pool {
+                            Some(pool) => {
+                                pool.install(|| OptExtractor::new_parallel(graph, cost_function))
+                            }
+                            None => OptExtractor::new(graph, cost_function),
+                        };
+                        let cost_cache = &extractor.cost_function().cache;
+                        cache = (cost_cache.hits(), cost_cache.lookups());
+                        Either::Left(extractor)
+                    }
+                };
                 t_extractor = start_time.elapsed().as_millis();
                 OptExtractionNetwork(extractor, outputs)
             },
             |ntk| {
+                if let Some(class) = ntk.unextractable_class() {
+                    compile_error = Some(CompileError::NotExtractable { node: class.into() });
+                    return Program::new(architecture, Vec::new());
+                }
+                if skip_compilation {
+                    return Program::new(architecture, Vec::new());
+                }
                 let start_time = Instant::now();
-                let program = compile(architecture, &ntk.with_backward_edges())
-                    .expect("network should be compilable");
+                let program = compile(architecture, &ntk.with_backward_edges());
                 t_compiler = start_time.elapsed().as_millis();
-                if settings.print_program || settings.verbose {
-                    if settings.verbose {
-                        println!("== Program")
-                    }
-                    println!("{program}");
-                }
-                program
+                program.unwrap_or_else(|err| {
+                    compile_error = Some(err);
+                    Program::new(architecture, Vec::new())
+                })
             },
         );
-        if settings.verbose {
-            println!("== Timings");
-            println!("t_runner: {t_runner}ms");
-            println!("t_extractor: {t_extractor}ms");
-            println!("t_compiler: {t_compiler}ms");
-        }
-        CompilingReceiverResult {
+        self.t_extractor += t_extractor;
+        self.t_compiler += t_compiler;
+        self.cache_hits += cache.0;
+        self.cache_lookups += cache.1;
+        Pass {
             output,
-            t_runner,
-            t_extractor,
-            t_compiler,
+            compile_error,
+            stop_reason,
+            iterations,
         }
-    })
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -203,6 +427,84 @@ struct CompilerSettings {
     print_program: bool,
     verbose: bool,
     rewrite: bool,
+    architecture: ArchitecturePreset,
+    objective: ExtractionObjective,
+    /// check the extracted network and the compiled program against the received network
+    verify: bool,
+
+    /// limits of the equality saturation
+    iter_limit: u64,
+    node_limit: u64,
+    time_limit_ms: u64,
+    scheduler: Scheduler,
+    /// initial match limit and ban length of [Scheduler::Backoff]
+    match_limit: u64,
+    ban_length: u64,
+
+    /// file containing rules to rewrite with in addition to the built-in ones (see
+    /// [RuleSet::load]), or null
+    rules_file: *const c_char,
+    /// comma-separated names of rules not to rewrite with, or null
+    disabled_rules: *const c_char,
+
+    /// merge e-classes proven to be equivalent between iterations of the equality saturation, see
+    /// [merge_equivalent_classes]
+    functional_reduction: bool,
+
+    /// maximum number of rounds of rewriting, extracting and compiling, where each round starts
+    /// from the network extracted in the previous one (at most [MAX_ROUNDS], 0 is the same as 1)
+    rounds: u64,
+
+    /// [ExtractionMethod::Ilp] falls back to greedy extraction for e-graphs of more than
+    /// [MAX_ILP_NODES] nodes or if the solver fails
+    extraction: ExtractionMethod,
+
+    /// number of threads evaluating the costs of nodes during greedy extraction, see
+    /// [OptExtractor::new_parallel], where 0 and 1 evaluate them sequentially
+    extraction_threads: u64,
+
+    /// number of rows of the subarray to allocate the rows of the compiled program in (see
+    /// [Program::allocate_rows]), or 0 to skip the allocation, see [CompilerSettings::subarray]
+    subarray_rows: u64,
+    /// files to write the DRAMPower command trace of the allocated program and the rows activated
+    /// by it to (see [trace]), or null, only written if [CompilerSettings::subarray_rows] is set
+    trace_file: *const c_char,
+    row_map_file: *const c_char,
+
+    /// comma-separated names of optional built-in rules to rewrite with (see
+    /// [RuleSet::enable]), or null
+    enabled_rules: *const c_char,
+}
+
+/// The files to write the trace of an allocated program to, see [CompilerSettings::trace_file].
+#[derive(Debug, Clone, Default)]
+struct TraceFiles {
+    trace: Option<PathBuf>,
+    row_map: Option<PathBuf>,
+}
+
+/// The rewrite scheduler of the equality saturation.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+#[allow(dead_code)] // only constructed over FFI
+enum Scheduler {
+    /// applies all rewrites in each iteration, see [SimpleScheduler]
+    Simple,
+    /// temporarily bans rewrites that match too often, see [BackoffScheduler]
+    Backoff,
+}
+
+/// Why the equality saturation stopped.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+enum RunnerStopReason {
+    /// rewriting is disabled in the settings
+    NotRun,
+    Saturated,
+    IterationLimit,
+    NodeLimit,
+    TimeLimit,
+    Other,
 }
 
 #[repr(C)]
@@ -212,43 +514,606 @@ struct CompilerStatistics {
     egraph_size: u64,
 
     instruction_count: u64,
+    /// estimated latency of the program in ns
+    latency: f64,
+    /// estimated energy of the program in nJ
+    energy: f64,
 
     t_runner: u64,
     t_extractor: u64,
     t_compiler: u64,
+
+    /// true iff verification was enabled and failed
+    verification_failed: bool,
+
+    /// [CompilerStatus::Ok] unless the network could not be compiled, in which case all other
+    /// statistics of the program are zero
+    status: CompilerStatus,
+    /// null-terminated description of the error if the network could not be compiled
+    error_message: [c_char; ERROR_MESSAGE_LEN],
+
+    runner_stop_reason: RunnerStopReason,
+    runner_iterations: u64,
+    /// number of e-classes merged by the functional reduction
+    functional_merges: u64,
+
+    /// the number of rounds and the instruction count of the program compiled in each of them,
+    /// the program of the last round that improved on the previous ones is returned
+    rounds: u64,
+    round_instructions: [u64; MAX_ROUNDS],
+
+    /// share of the partial networks costed during greedy extraction that were not compiled again
+    /// because an identical one was compiled before, or zero if none were costed
+    compilation_cache_hit_rate: f64,
+
+    /// number of physical rows used for spilling if [CompilerSettings::subarray_rows] is set
+    spill_rows: u64,
+}
+
+/// Maximum length of [CompilerStatistics::error_message] including the null terminator.
+const ERROR_MESSAGE_LEN: usize = 256;
+/// Maximum number of rounds, see [CompilerSettings::rounds].
+const MAX_ROUNDS: usize = 16;
+/// Maximum number of e-graph nodes for [ExtractionMethod::Ilp]. The solver has no time limit, so
+/// this keeps the branch and bound over the binary node variables small enough to finish quickly.
+const MAX_ILP_NODES: usize = 250;
+
+/// Whether compilation succeeded or the kind of [CompileError] that occurred otherwise.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+enum CompilerStatus {
+    Ok,
+    InvalidLeaf,
+    RowOccupied,
+    NotACandidate,
+    NotAMaj,
+    NoMajOperation,
+    NoFreeDcc,
+    SignalUnavailable,
+    /// the rules file could not be loaded or a disabled rule does not exist
+    InvalidRules,
+    /// the rows of the compiled program could not be allocated in the subarray, e.g. because it
+    /// has too few rows to spill to
+    AllocationFailed,
+    NotExtractable,
+}
+
+/// Whether a program computes the function of a network, see [ambit_check_program_ffi].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+enum ProgramCheckStatus {
+    Ok,
+    /// the program could not be parsed
+    InvalidProgram,
+    /// the program could not be simulated, e.g. because it reads an uninitialized row
+    SimulationFailed,
+    /// an output of the program differs from that of the network
+    Mismatch,
+}
+
+#[repr(C)]
+struct ProgramCheckFFI {
+    status: ProgramCheckStatus,
+    /// number of instructions of the program if it could be parsed
+    instruction_count: u64,
+    /// null-terminated description of the error unless the status is [ProgramCheckStatus::Ok]
+    error_message: [c_char; ERROR_MESSAGE_LEN],
+}
+
+/// The statistics of a compilation together with the compiled program.
+#[repr(C)]
+struct CompilerOutputFFI {
+    statistics: CompilerStatistics,
+    program: ProgramFFI,
+}
+
+/// # Safety
+/// `rules_file`, `disabled_rules`, `trace_file`, `row_map_file` and `enabled_rules` of the
+/// settings have to be either null or valid, null-terminated strings.
+#[no_mangle]
+unsafe extern "C" fn ambit_rewrite_ffi(
+    settings: CompilerSettings,
+    receiver: MigReceiverFFI<()>,
+) -> MigReceiverFFI<CompilerStatistics> {
+    rewrite_ffi(settings.architecture.architecture(), settings, receiver)
+}
+
+/// # Safety
+/// See [ambit_rewrite_ffi].
+#[no_mangle]
+unsafe extern "C" fn ambit_compile_ffi(
+    settings: CompilerSettings,
+) -> MigReceiverFFI<CompilerStatistics> {
+    compile_ffi(settings.architecture.architecture(), settings)
 }
 
+/// Same as [ambit_rewrite_ffi], but compiles against the given architecture instead of the preset
+/// selected in the settings.
+///
+/// # Safety
+/// `architecture` has to be a valid pointer obtained from [ambit_architecture_load_ffi] that is not
+/// freed before the returned receiver is done. The settings have to satisfy the requirements of
+/// [ambit_rewrite_ffi].
 #[no_mangle]
-extern "C" fn ambit_rewrite_ffi(
+unsafe extern "C" fn ambit_rewrite_with_architecture_ffi(
+    architecture: *const Architecture,
     settings: CompilerSettings,
     receiver: MigReceiverFFI<()>,
 ) -> MigReceiverFFI<CompilerStatistics> {
-    let receiver =
-        compiling_receiver(&ARCHITECTURE, REWRITE_RULES.as_slice(), settings).map(|res| {
+    rewrite_ffi(&*architecture, settings, receiver)
+}
+
+/// Same as [ambit_compile_ffi], but compiles against the given architecture instead of the preset
+/// selected in the settings.
+///
+/// # Safety
+/// `architecture` has to be a valid pointer obtained from [ambit_architecture_load_ffi] that is not
+/// freed before the returned receiver is done. The settings have to satisfy the requirements of
+/// [ambit_rewrite_ffi].
+#[no_mangle]
+unsafe extern "C" fn ambit_compile_with_architecture_ffi(
+    architecture: *const Architecture,
+    settings: CompilerSettings,
+) -> MigReceiverFFI<CompilerStatistics> {
+    compile_ffi(&*architecture, settings)
+}
+
+/// Same as [ambit_compile_ffi], but also returns the compiled program, which has to be freed with
+/// `ambit_program_free_ffi`.
+///
+/// # Safety
+/// See [ambit_rewrite_ffi].
+#[no_mangle]
+unsafe extern "C" fn ambit_compile_program_ffi(
+    settings: CompilerSettings,
+) -> MigReceiverFFI<CompilerOutputFFI> {
+    compile_program_ffi(settings.architecture.architecture(), settings)
+}
+
+/// Same as [ambit_compile_program_ffi], but compiles against the given architecture instead of the
+/// preset selected in the settings.
+///
+/// # Safety
+/// `architecture` has to be a valid pointer obtained from [ambit_architecture_load_ffi] that is not
+/// freed before the returned receiver is done. The settings have to satisfy the requirements of
+/// [ambit_rewrite_ffi].
+#[no_mangle]
+unsafe extern "C" fn ambit_compile_program_with_architecture_ffi(
+    architecture: *const Architecture,
+    settings: CompilerSettings,
+) -> MigReceiverFFI<CompilerOutputFFI> {
+    compile_program_ffi(&*architecture, settings)
+}
+
+/// Returns a receiver that checks the program given in its textual representation (see
+/// [Program::parse]) against the received network by simulation, e.g. to validate hand-optimized
+/// reference programs.
+///
+/// # Safety
+/// `source` has to be a valid, null-terminated string.
+#[no_mangle]
+unsafe extern "C" fn ambit_check_program_ffi(
+    preset: ArchitecturePreset,
+    source: *const c_char,
+) -> MigReceiverFFI<ProgramCheckFFI> {
+    check_program_ffi(preset.architecture(), source)
+}
+
+/// Same as [ambit_check_program_ffi], but parses the program for the given architecture instead of
+/// a preset.
+///
+/// # Safety
+/// `architecture` has to be a valid pointer obtained from [ambit_architecture_load_ffi] that is not
+/// freed before the returned receiver is done. `source` has to be a valid, null-terminated string.
+#[no_mangle]
+unsafe extern "C" fn ambit_check_program_with_architecture_ffi(
+    architecture: *const Architecture,
+    source: *const c_char,
+) -> MigReceiverFFI<ProgramCheckFFI> {
+    check_program_ffi(&*architecture, source)
+}
+
+/// Loads an architecture description (see [Architecture::load]). Returns a null pointer and prints
+/// the reason to stderr if the architecture could not be loaded.
+///
+/// # Safety
+/// `path` has to be a valid, null-terminated string.
+#[no_mangle]
+unsafe extern "C" fn ambit_architecture_load_ffi(path: *const c_char) -> *mut Architecture {
+    let Ok(path) = CStr::from_ptr(path).to_str() else {
+        eprintln!("architecture path is not valid UTF-8");
+        return ptr::null_mut();
+    };
+    match Architecture::load(path) {
+        Ok(architecture) => Box::into_raw(Box::new(architecture)),
+        Err(err) => {
+            eprintln!("{err}");
+            ptr::null_mut()
+        }
+    }
+}
+
+/// # Safety
+/// `architecture` has to be either null or a pointer obtained from [ambit_architecture_load_ffi]
+/// that has not been freed yet.
+#[no_mangle]
+unsafe extern "C" fn ambit_architecture_free_ffi(architecture: *mut Architecture) {
+    if !architecture.is_null() {
+        drop(Box::from_raw(architecture));
+    }
+}
+
+unsafe fn rewrite_ffi(
+    architecture: &'static Architecture,
+    settings: CompilerSettings,
+    receiver: MigReceiverFFI<()>,
+) -> MigReceiverFFI<CompilerStatistics> {
+    if settings.functional_reduction {
+        rewrite_with_ffi(
+            architecture,
+            settings,
+            receiver,
+            FunctionalAnalysis,
+            Some(merge_equivalent_classes),
+        )
+    } else {
+        rewrite_with_ffi(architecture, settings, receiver, ConstantFolding, None)
+    }
+}
+
+unsafe fn compile_ffi(
+    architecture: &'static Architecture,
+    settings: CompilerSettings,
+) -> MigReceiverFFI<CompilerStatistics> {
+    if settings.functional_reduction {
+        compile_with_ffi(
+            architecture,
+            settings,
+            FunctionalAnalysis,
+            Some(merge_equivalent_classes),
+        )
+    } else {
+        compile_with_ffi(architecture, settings, ConstantFolding, None)
+    }
+}
+
+unsafe fn compile_program_ffi(
+    architecture: &'static Architecture,
+    settings: CompilerSettings,
+) -> MigReceiverFFI<CompilerOutputFFI> {
+    if settings.functional_reduction {
+        compile_program_with_ffi(
+            architecture,
+            settings,
+            FunctionalAnalysis,
+            Some(merge_equivalent_classes),
+        )
+    } else {
+        compile_program_with_ffi(architecture, settings, ConstantFolding, None)
+    }
+}
+
+/// Same as [rewrite_ffi], but with the given analysis. The signatures and truth tables of
+/// [FunctionalAnalysis] are only needed by the functional reduction, so it is only used then.
+unsafe fn rewrite_with_ffi<A>(
+    architecture: &'static Architecture,
+    settings: CompilerSettings,
+    receiver: MigReceiverFFI<()>,
+    analysis: A,
+    reduce: Option<Reduction<A>>,
+) -> MigReceiverFFI<CompilerStatistics>
+where
+    A: ConstantAnalysis + Clone + Default + Sync + 'static,
+    A::Data: Clone + Sync,
+{
+    let receiver = compiling_receiver(
+        architecture,
+        analysis,
+        settings.rule_set(architecture),
+        reduce,
+        settings,
+        settings.trace_files(),
+    )
+    .map(|res| {
+        // an incomplete network cannot be sent, the receiver then remains empty
+        if res.output.borrow_ntk().unextractable_class().is_none() {
             res.output.borrow_ntk().send(receiver);
-            CompilerStatistics::from_result(res)
-        });
+        }
+        CompilerStatistics::from_result(res)
+    });
     MigReceiverFFI::new(receiver)
 }
 
-#[no_mangle]
-extern "C" fn ambit_compile_ffi(settings: CompilerSettings) -> MigReceiverFFI<CompilerStatistics> {
-    let receiver = compiling_receiver(&ARCHITECTURE, REWRITE_RULES.as_slice(), settings)
-        .map(CompilerStatistics::from_result);
+/// Same as [compile_ffi], but with the given analysis, see [rewrite_with_ffi].
+unsafe fn compile_with_ffi<A>(
+    architecture: &'static Architecture,
+    settings: CompilerSettings,
+    analysis: A,
+    reduce: Option<Reduction<A>>,
+) -> MigReceiverFFI<CompilerStatistics>
+where
+    A: ConstantAnalysis + Clone + Default + Sync + 'static,
+    A::Data: Clone + Sync,
+{
+    let receiver = compiling_receiver(
+        architecture,
+        analysis,
+        settings.rule_set(architecture),
+        reduce,
+        settings,
+        settings.trace_files(),
+    )
+    .map(CompilerStatistics::from_result);
     MigReceiverFFI::new(receiver)
 }
 
+/// Same as [compile_program_ffi], but with the given analysis, see [rewrite_with_ffi].
+unsafe fn compile_program_with_ffi<A>(
+    architecture: &'static Architecture,
+    settings: CompilerSettings,
+    analysis: A,
+    reduce: Option<Reduction<A>>,
+) -> MigReceiverFFI<CompilerOutputFFI>
+where
+    A: ConstantAnalysis + Clone + Default + Sync + 'static,
+    A::Data: Clone + Sync,
+{
+    let receiver = compiling_receiver(
+        architecture,
+        analysis,
+        settings.rule_set(architecture),
+        reduce,
+        settings,
+        settings.trace_files(),
+    )
+    .map(|res| {
+        let program = ProgramFFI::new(res.output.borrow_program());
+        CompilerOutputFFI {
+            statistics: CompilerStatistics::from_result(res),
+            program,
+        }
+    });
+    MigReceiverFFI::new(receiver)
+}
+
+unsafe fn check_program_ffi(
+    architecture: &'static Architecture,
+    source: *const c_char,
+) -> MigReceiverFFI<ProgramCheckFFI> {
+    let source = CStr::from_ptr(source).to_string_lossy().into_owned();
+    let receiver = EGraph::<MigLanguage, ()>::new(()).map(move |(graph, outputs)| {
+        let program = match Program::parse(architecture, &source) {
+            Ok(program) => program,
+            Err(err) => {
+                return ProgramCheckFFI::new(ProgramCheckStatus::InvalidProgram, 0, Some(err));
+            }
+        };
+        let instruction_count = program.instructions.len() as u64;
+        let (status, error) = match verify_program(&graph, &outputs, &program) {
+            Ok(()) => (ProgramCheckStatus::Ok, None),
+            Err(err @ VerificationError::Simulation(_)) => {
+                (ProgramCheckStatus::SimulationFailed, Some(err))
+            }
+            Err(err @ VerificationError::Mismatches(_)) => {
+                (ProgramCheckStatus::Mismatch, Some(err))
+            }
+        };
+        ProgramCheckFFI::new(status, instruction_count, error)
+    });
+    MigReceiverFFI::new(receiver)
+}
+
+impl ProgramCheckFFI {
+    fn new(
+        status: ProgramCheckStatus,
+        instruction_count: u64,
+        error: Option<impl ToString>,
+    ) -> Self {
+        Self {
+            status,
+            instruction_count,
+            error_message: error_message(error.map(|err| err.to_string())),
+        }
+    }
+}
+
 impl CompilerStatistics {
-    fn from_result(res: CompilingReceiverResult) -> Self {
+    fn from_result<A: ConstantAnalysis>(res: CompilingReceiverResult<A>) -> Self {
         let graph = res.output.borrow_graph();
+        let program = res.output.borrow_program();
         CompilerStatistics {
             egraph_classes: graph.number_of_classes() as u64,
             egraph_nodes: graph.total_number_of_nodes() as u64,
             egraph_size: graph.total_size() as u64,
-            instruction_count: res.output.borrow_program().instructions.len() as u64,
+            instruction_count: program.instructions.len() as u64,
+            latency: program.latency(),
+            energy: program.energy(),
             t_runner: res.t_runner as u64,
             t_extractor: res.t_extractor as u64,
             t_compiler: res.t_compiler as u64,
+            verification_failed: res.verification_failed,
+            status: match (&res.rules_error, &res.compile_error, &res.allocation_error) {
+                (Some(_), _, _) => CompilerStatus::InvalidRules,
+                (None, Some(err), _) => err.into(),
+                (None, None, Some(_)) => CompilerStatus::AllocationFailed,
+                (None, None, None) => CompilerStatus::Ok,
+            },
+            error_message: error_message(
+                match (&res.rules_error, &res.compile_error, &res.allocation_error) {
+                    (Some(err), _, _) => Some(err.to_string()),
+                    (None, Some(err), _) => Some(err.to_string()),
+                    (None, None, err) => err.as_ref().map(AllocationError::to_string),
+                },
+            ),
+            runner_stop_reason: res.stop_reason,
+            runner_iterations: res.iterations as u64,
+            functional_merges: res.functional_merges as u64,
+            rounds: res.round_instructions.len() as u64,
+            round_instructions: {
+                let mut round_instructions = [0; MAX_ROUNDS];
+                for (dst, src) in round_instructions.iter_mut().zip(&res.round_instructions) {
+                    *dst = *src as u64;
+                }
+                round_instructions
+            },
+            compilation_cache_hit_rate: if res.cache_lookups == 0 {
+                0.0
+            } else {
+                res.cache_hits as f64 / res.cache_lookups as f64
+            },
+            spill_rows: res.spill_rows as u64,
+        }
+    }
+}
+
+impl CompilerSettings {
+    /// Returns the built-in rules for the given architecture together with the `enabled_rules`,
+    /// extended by the rules of `rules_file` and without the `disabled_rules`.
+    ///
+    /// # Safety
+    /// `rules_file`, `disabled_rules` and `enabled_rules` have to be either null or valid,
+    /// null-terminated strings.
+    unsafe fn rule_set<A: Analysis<MigLanguage>>(
+        &self,
+        architecture: &Architecture,
+    ) -> Result<RuleSet<A>, RuleSetError> {
+        let mut rules = RuleSet::builtin(architecture);
+        for name in rule_names(self.enabled_rules) {
+            rules.enable(&name)?;
+        }
+        if !self.rules_file.is_null() {
+            rules.load(&*CStr::from_ptr(self.rules_file).to_string_lossy())?;
+        }
+        for name in rule_names(self.disabled_rules) {
+            rules.disable(&name)?;
+        }
+        Ok(rules)
+    }
+
+    /// Returns the files to write the trace to.
+    ///
+    /// # Safety
+    /// `trace_file` and `row_map_file` have to be either null or valid, null-terminated strings.
+    unsafe fn trace_files(&self) -> TraceFiles {
+        let path = |file: *const c_char| {
+            (!file.is_null()).then(|| PathBuf::from(&*CStr::from_ptr(file).to_string_lossy()))
+        };
+        TraceFiles {
+            trace: path(self.trace_file),
+            row_map: path(self.row_map_file),
+        }
+    }
+
+    /// Returns the subarray with [CompilerSettings::subarray_rows] rows whose B-group has exactly
+    /// the row addresses required by the given architecture, followed by the two constant rows, or
+    /// none if rows are not allocated.
+    fn subarray(&self, architecture: &Architecture) -> Option<Subarray> {
+        (self.subarray_rows != 0).then(|| Subarray {
+            rows: self.subarray_rows.try_into().unwrap_or(u32::MAX),
+            b_group_rows: b_group_addresses(architecture),
+            ..Subarray::default()
+        })
+    }
+
+    /// Returns a thread pool with [CompilerSettings::extraction_threads] threads, or none if
+    /// extraction is sequential or the pool could not be created.
+    fn thread_pool(&self) -> Option<ThreadPool> {
+        if self.extraction_threads <= 1 {
+            return None;
+        }
+        ThreadPoolBuilder::new()
+            .num_threads(self.extraction_threads as usize)
+            .build()
+            .inspect_err(|err| eprintln!("{err}, extracting sequentially"))
+            .ok()
+    }
+
+    fn runner<A: Analysis<MigLanguage> + Default>(&self) -> Runner<MigLanguage, A> {
+        let runner = Runner::default()
+            .with_iter_limit(self.iter_limit as usize)
+            .with_node_limit(self.node_limit as usize)
+            .with_time_limit(Duration::from_millis(self.time_limit_ms));
+        match self.scheduler {
+            Scheduler::Simple => runner.with_scheduler(SimpleScheduler),
+            Scheduler::Backoff => runner.with_scheduler(
+                BackoffScheduler::default()
+                    .with_initial_match_limit(self.match_limit as usize)
+                    .with_ban_length(self.ban_length as usize),
+            ),
+        }
+    }
+}
+
+impl TraceFiles {
+    /// Writes the trace of the given program to the files that are set, see [trace].
+    fn write(&self, program: &PhysicalProgram) -> io::Result<()> {
+        let settings = TraceSettings::default();
+        if let Some(path) = &self.trace {
+            program.write_trace(&settings, BufWriter::new(File::create(path)?))?;
+        }
+        if let Some(path) = &self.row_map {
+            program.write_row_map(&settings, BufWriter::new(File::create(path)?))?;
+        }
+        Ok(())
+    }
+}
+
+impl From<Option<&StopReason>> for RunnerStopReason {
+    fn from(value: Option<&StopReason>) -> Self {
+        match value {
+            None => Self::NotRun,
+            Some(StopReason::Saturated) => Self::Saturated,
+            Some(StopReason::IterationLimit(_)) => Self::IterationLimit,
+            Some(StopReason::NodeLimit(_)) => Self::NodeLimit,
+            Some(StopReason::TimeLimit(_)) => Self::TimeLimit,
+            Some(StopReason::Other(_)) => Self::Other,
+        }
+    }
+}
+
+impl From<&CompileError> for CompilerStatus {
+    fn from(value: &CompileError) -> Self {
+        match value {
+            CompileError::InvalidLeaf { .. } => Self::InvalidLeaf,
+            CompileError::RowOccupied { .. } => Self::RowOccupied,
+            CompileError::NotACandidate { .. } => Self::NotACandidate,
+            CompileError::NotAMaj { .. } => Self::NotAMaj,
+            CompileError::NoMajOperation { .. } => Self::NoMajOperation,
+            CompileError::NoFreeDcc { .. } => Self::NoFreeDcc,
+            CompileError::SignalUnavailable { .. } => Self::SignalUnavailable,
+            CompileError::NotExtractable { .. } => Self::NotExtractable,
+        }
+    }
+}
+
+/// Returns the names of the given comma-separated list of rule names, which may be null.
+///
+/// # Safety
+/// `names` has to be either null or a valid, null-terminated string.
+unsafe fn rule_names(names: *const c_char) -> Vec<String> {
+    if names.is_null() {
+        return Vec::new();
+    }
+    CStr::from_ptr(names)
+        .to_string_lossy()
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Returns the given error message as a null-terminated string, truncated to fit
+/// [ERROR_MESSAGE_LEN].
+fn error_message(error: Option<String>) -> [c_char; ERROR_MESSAGE_LEN] {
+    let mut message = [0; ERROR_MESSAGE_LEN];
+    if let Some(error) = error {
+        for (dst, src) in message[..ERROR_MESSAGE_LEN - 1]
+            .iter_mut()
+            .zip(error.bytes())
+        {
+            *dst = src as c_char;
         }
     }
+    message
 }
@@ -0,0 +1,138 @@
+use super::{Architecture, BitwiseOperand};
+use std::sync::LazyLock;
+
+/// Architectures built into the compiler, selectable via the compiler settings.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+#[allow(dead_code)] // only constructed over FFI
+pub enum ArchitecturePreset {
+    /// The B-group of the original Ambit proposal, see [AMBIT].
+    Ambit,
+    /// The Ambit B-group with additional inverted triple-row activations, see
+    /// [AMBIT_INVERTED_TRA].
+    AmbitInvertedTra,
+    /// The smallest architecture that can be compiled for, see [MINIMAL].
+    Minimal,
+}
+
+impl ArchitecturePreset {
+    pub fn architecture(self) -> &'static Architecture {
+        match self {
+            Self::Ambit => &AMBIT,
+            Self::AmbitInvertedTra => &AMBIT_INVERTED_TRA,
+            Self::Minimal => &MINIMAL,
+        }
+    }
+}
+
+/// The Ambit B-group with the four T rows T0-T3, two DCC rows and the eight multi-row addresses
+/// B8-B15.
+pub static AMBIT: LazyLock<Architecture> = LazyLock::new(|| {
+    use BitwiseOperand::*;
+    Architecture::new(
+        vec![
+            // 2 rows
+            vec![
+                DCC {
+                    index: 0,
+                    inverted: true,
+                },
+                T(0),
+            ],
+            vec![
+                DCC {
+                    inverted: true,
+                    index: 1,
+                },
+                T(1),
+            ],
+            vec![T(2), T(3)],
+            vec![T(0), T(3)],
+            // 3 rows
+            vec![T(0), T(1), T(2)],
+            vec![T(1), T(2), T(3)],
+            vec![
+                DCC {
+                    index: 0,
+                    inverted: false,
+                },
+                T(1),
+                T(2),
+            ],
+            vec![
+                DCC {
+                    index: 1,
+                    inverted: false,
+                },
+                T(0),
+                T(3),
+            ],
+        ],
+        2,
+    )
+    .expect("preset should be valid")
+});
+
+/// The Ambit B-group extended by the triple-row activations `[~DCC0, T1, T2]` and `[~DCC1, T0, T3]`
+/// through the negated wordlines of the DCC rows, which allow computing MAJ of a negated operand
+/// without first copying it out of the DCC row. They are not taken from a published decoder, but
+/// mirror the activations `[DCC0, T1, T2]` and `[DCC1, T0, T3]` of Ambit.
+pub static AMBIT_INVERTED_TRA: LazyLock<Architecture> = LazyLock::new(|| {
+    use BitwiseOperand::*;
+    let mut multi_activations = AMBIT.multi_activations.clone();
+    multi_activations.extend([
+        vec![
+            DCC {
+                index: 0,
+                inverted: true,
+            },
+            T(1),
+            T(2),
+        ],
+        vec![
+            DCC {
+                index: 1,
+                inverted: true,
+            },
+            T(0),
+            T(3),
+        ],
+    ]);
+    Architecture::new(multi_activations, 2).expect("preset should be valid")
+});
+
+/// A single triple-row activation (as available on commodity DRAM via ComputeDRAM-style command
+/// sequences) and a single DCC row for inverting signals. No activation includes the DCC row, it is
+/// only addressed on its own: a signal is inverted by copying it to `DCC0` and copying `~DCC0` to
+/// its destination, so the architecture cannot be compiled for without it.
+pub static MINIMAL: LazyLock<Architecture> = LazyLock::new(|| {
+    use BitwiseOperand::*;
+    Architecture::new(vec![vec![T(0), T(1), T(2)]], 1).expect("preset should be valid")
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambit::architecture::ArchitectureError;
+
+    #[test]
+    fn presets_are_valid() {
+        for preset in [
+            ArchitecturePreset::Ambit,
+            ArchitecturePreset::AmbitInvertedTra,
+            ArchitecturePreset::Minimal,
+        ] {
+            preset.architecture().validate().unwrap();
+        }
+    }
+
+    #[test]
+    fn minimal_requires_its_dcc_row() {
+        use BitwiseOperand::*;
+        let err = Architecture::new(vec![vec![T(0), T(1), T(2)]], 0).unwrap_err();
+        assert!(err
+            .0
+            .iter()
+            .any(|err| matches!(err, ArchitectureError::NoFreeDcc { .. })));
+    }
+}
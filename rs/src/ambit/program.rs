@@ -1,4 +1,4 @@
-use super::{Architecture, BitwiseOperand, BitwiseRow, Row, Rows};
+use super::{Architecture, BitwiseOperand, BitwiseRow, CompileError, Row, Rows};
 use eggmock::{Id, Mig, NetworkWithBackwardEdges, Signal};
 use std::fmt::{Display, Formatter};
 use std::ops::{Deref, DerefMut};
@@ -116,35 +116,46 @@ impl<'a> ProgramState<'a> {
     pub fn new(
         architecture: &'a Architecture,
         network: &impl NetworkWithBackwardEdges<Node = Mig>,
-    ) -> Self {
-        Self {
+    ) -> Result<Self, CompileError> {
+        Ok(Self {
             program: Program::new(architecture, Vec::new()),
-            rows: Rows::new(network, architecture),
-        }
+            rows: Rows::new(network, architecture)?,
+        })
     }
 
-    pub fn maj(&mut self, op: usize, out_signal: Signal, out_address: Option<Address>) {
+    pub fn maj(
+        &mut self,
+        op: usize,
+        out_signal: Signal,
+        out_address: Option<Address>,
+    ) -> Result<(), CompileError> {
         let operands = &self.architecture.multi_activations[op];
         for operand in operands {
-            self.set_signal(SingleRowAddress::Bitwise(*operand), out_signal);
+            self.set_signal(SingleRowAddress::Bitwise(*operand), out_signal)?;
         }
         let instruction = match out_address {
             Some(out) => Instruction::AAP(BitwiseAddress::Multiple(op).into(), out),
             None => Instruction::AP(BitwiseAddress::Multiple(op).into()),
         };
-        self.instructions.push(instruction)
+        self.instructions.push(instruction);
+        Ok(())
     }
 
-    pub fn signal_copy(&mut self, signal: Signal, target: SingleRowAddress, intermediate_dcc: u8) {
+    pub fn signal_copy(
+        &mut self,
+        signal: Signal,
+        target: SingleRowAddress,
+        intermediate_dcc: u8,
+    ) -> Result<(), CompileError> {
         {
             // if any row contains the signal, then this is easy, simply copy the row into the
             // target operand
             let signal_row = self.rows.get_rows(signal).next();
             if let Some(signal_row) = signal_row {
-                self.set_signal(target, signal);
+                self.set_signal(target, signal)?;
                 self.instructions
                     .push(Instruction::AAP(signal_row.into(), target.into()));
-                return;
+                return Ok(());
             }
         }
         // otherwise we need to search the inverted signal and take a DCC row if possible, otherwise
@@ -158,11 +169,11 @@ impl<'a> ProgramState<'a> {
             inverted_signal_row = Some(row);
         }
         let inverted_signal_row =
-            inverted_signal_row.expect("inverted signal row should be present");
+            inverted_signal_row.ok_or(CompileError::SignalUnavailable { signal })?;
 
         if let SingleRowAddress::Bitwise(BitwiseOperand::DCC { inverted, index }) = target {
             // if the target is a DCC operand, we can simply copy over the signal
-            self.set_signal(target, signal);
+            self.set_signal(target, signal)?;
             self.instructions.push(Instruction::AAP(
                 inverted_signal_row.into(),
                 BitwiseOperand::DCC {
@@ -171,13 +182,13 @@ impl<'a> ProgramState<'a> {
                 }
                 .into(),
             ));
-            return;
+            return Ok(());
         }
 
         if let Row::Bitwise(BitwiseRow::DCC(dcc)) = inverted_signal_row {
             // alrighty, that's great, the inverted DCC row contains our signal
             // let's copy that over
-            self.set_signal(target, signal);
+            self.set_signal(target, signal)?;
             self.instructions.push(Instruction::AAP(
                 BitwiseOperand::DCC {
                     inverted: true,
@@ -186,7 +197,7 @@ impl<'a> ProgramState<'a> {
                 .into(),
                 target.into(),
             ));
-            return;
+            return Ok(());
         }
         // this is the very sad case in which we have to use the intermediate DCC row to create the
         // signal from its inverse
@@ -195,7 +206,7 @@ impl<'a> ProgramState<'a> {
             inverted: false,
             index: intermediate_dcc,
         });
-        self.set_signal(intermediate_dcc_addr, signal.invert());
+        self.set_signal(intermediate_dcc_addr, signal.invert())?;
         self.instructions.push(Instruction::AAP(
             inverted_signal_row.into(),
             intermediate_dcc_addr.into(),
@@ -206,11 +217,12 @@ impl<'a> ProgramState<'a> {
             inverted: true,
             index: intermediate_dcc,
         };
-        self.set_signal(target, signal);
+        self.set_signal(target, signal)?;
         self.instructions.push(Instruction::AAP(
             inv_intermediate_dcc_operand.into(),
             target.into(),
         ));
+        Ok(())
     }
 
     /// Sets the value of the operand in `self.rows` to the given signal. If that removes the last
@@ -218,14 +230,19 @@ impl<'a> ProgramState<'a> {
     /// previous signal
     /// **ALWAYS** call this before inserting the actual instruction, otherwise the spill code will
     /// spill the wrong value
-    fn set_signal(&mut self, address: SingleRowAddress, signal: Signal) {
+    fn set_signal(
+        &mut self,
+        address: SingleRowAddress,
+        signal: Signal,
+    ) -> Result<(), CompileError> {
         if let Some(previous_signal) = self.rows.set_signal(address, signal) {
             if !self.rows.contains_id(previous_signal.node_id()) {
-                let spill_id = self.rows.add_spill(previous_signal);
+                let spill_id = self.rows.add_spill(previous_signal)?;
                 self.instructions
                     .push(Instruction::AAP(address.into(), Address::Spill(spill_id)));
             }
         }
+        Ok(())
     }
 
     pub fn free_id_rows(&mut self, id: Id) {
@@ -371,18 +388,6 @@ impl From<SingleRowAddress> for Address {
 
 impl Display for Program<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let write_operand = |f: &mut Formatter<'_>, o: &BitwiseOperand| -> std::fmt::Result {
-            match o {
-                BitwiseOperand::T(t) => write!(f, "T{t}"),
-                BitwiseOperand::DCC { inverted, index } => {
-                    if *inverted {
-                        write!(f, "~DCC{index}")
-                    } else {
-                        write!(f, "DCC{index}")
-                    }
-                }
-            }
-        };
         let write_address = |f: &mut Formatter<'_>, a: &Address| -> std::fmt::Result {
             match a {
                 Address::In(i) => write!(f, "I{i}"),
@@ -390,7 +395,7 @@ impl Display for Program<'_> {
                 Address::Spill(i) => write!(f, "S{i}"),
                 Address::Const(c) => write!(f, "C{}", if *c { "1" } else { "0" }),
                 Address::Bitwise(b) => match b {
-                    BitwiseAddress::Single(o) => write_operand(f, o),
+                    BitwiseAddress::Single(o) => write!(f, "{o}"),
                     BitwiseAddress::Multiple(id) => {
                         let operands = &self.architecture.multi_activations[*id];
                         for i in 0..operands.len() {
@@ -399,7 +404,7 @@ impl Display for Program<'_> {
                             } else {
                                 write!(f, ", ")?;
                             }
-                            write_operand(f, &operands[i])?;
+                            write!(f, "{}", operands[i])?;
                             if i == operands.len() - 1 {
                                 write!(f, "]")?;
                             }
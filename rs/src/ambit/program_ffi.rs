@@ -0,0 +1,146 @@
+use super::{Address, BitwiseAddress, BitwiseOperand, Instruction, Program};
+use std::ptr;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum OpcodeFFI {
+    Aap,
+    Ap,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AddressKindFFI {
+    Input,
+    Output,
+    Spill,
+    /// `index` is the value of the constant row, i.e. 0 or 1
+    Const,
+    T,
+    Dcc,
+    InvertedDcc,
+    /// `index` is the index of the multi-row activation in the architecture, the activated operands
+    /// are `operands[first_operand..first_operand + operand_count]` of the [ProgramFFI]
+    Multiple,
+}
+
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct AddressFFI {
+    kind: AddressKindFFI,
+    index: u64,
+    first_operand: u64,
+    operand_count: u64,
+}
+
+/// `to` is only meaningful for AAPs.
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct InstructionFFI {
+    opcode: OpcodeFFI,
+    from: AddressFFI,
+    to: AddressFFI,
+}
+
+/// A compiled program owned by the C++ side, which has to pass it to [ambit_program_free_ffi]
+/// once it is no longer used.
+#[derive(Debug)]
+#[repr(C)]
+pub struct ProgramFFI {
+    instructions: *mut InstructionFFI,
+    instruction_count: u64,
+    /// the operands of all multi-row activations, which are all single-row bitwise addresses
+    operands: *mut AddressFFI,
+    operand_count: u64,
+}
+
+impl ProgramFFI {
+    pub fn new(program: &Program) -> Self {
+        let mut operands = Vec::new();
+        let mut address = |address: Address| match address {
+            Address::In(i) => AddressFFI::single(AddressKindFFI::Input, i),
+            Address::Out(i) => AddressFFI::single(AddressKindFFI::Output, i),
+            Address::Spill(i) => AddressFFI::single(AddressKindFFI::Spill, i as u64),
+            Address::Const(value) => AddressFFI::single(AddressKindFFI::Const, value as u64),
+            Address::Bitwise(BitwiseAddress::Single(operand)) => AddressFFI::operand(operand),
+            Address::Bitwise(BitwiseAddress::Multiple(idx)) => {
+                let activation = &program.architecture.multi_activations[idx];
+                let first_operand = operands.len() as u64;
+                operands.extend(
+                    activation
+                        .iter()
+                        .map(|operand| AddressFFI::operand(*operand)),
+                );
+                AddressFFI {
+                    kind: AddressKindFFI::Multiple,
+                    index: idx as u64,
+                    first_operand,
+                    operand_count: activation.len() as u64,
+                }
+            }
+        };
+        let instructions: Box<[InstructionFFI]> = program
+            .instructions
+            .iter()
+            .map(|instruction| match *instruction {
+                Instruction::AAP(from, to) => InstructionFFI {
+                    opcode: OpcodeFFI::Aap,
+                    from: address(from),
+                    to: address(to),
+                },
+                Instruction::AP(from) => InstructionFFI {
+                    opcode: OpcodeFFI::Ap,
+                    from: address(from),
+                    to: AddressFFI::single(AddressKindFFI::Const, 0),
+                },
+            })
+            .collect();
+        let operands = operands.into_boxed_slice();
+        Self {
+            instruction_count: instructions.len() as u64,
+            instructions: Box::into_raw(instructions) as *mut InstructionFFI,
+            operand_count: operands.len() as u64,
+            operands: Box::into_raw(operands) as *mut AddressFFI,
+        }
+    }
+}
+
+impl AddressFFI {
+    fn single(kind: AddressKindFFI, index: u64) -> Self {
+        Self {
+            kind,
+            index,
+            first_operand: 0,
+            operand_count: 0,
+        }
+    }
+
+    fn operand(operand: BitwiseOperand) -> Self {
+        match operand {
+            BitwiseOperand::T(t) => Self::single(AddressKindFFI::T, t as u64),
+            BitwiseOperand::DCC { inverted, index } => Self::single(
+                if inverted {
+                    AddressKindFFI::InvertedDcc
+                } else {
+                    AddressKindFFI::Dcc
+                },
+                index as u64,
+            ),
+        }
+    }
+}
+
+/// # Safety
+/// `program` has to be obtained from one of the `ambit_compile_program*` functions and must not
+/// have been freed yet.
+#[no_mangle]
+unsafe extern "C" fn ambit_program_free_ffi(program: ProgramFFI) {
+    drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+        program.instructions,
+        program.instruction_count as usize,
+    )));
+    drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+        program.operands,
+        program.operand_count as usize,
+    )));
+}
@@ -1,4 +1,4 @@
-use super::{Architecture, BitwiseOperand, SingleRowAddress};
+use super::{Architecture, BitwiseOperand, CompileError, SingleRowAddress};
 use eggmock::{Id, Mig, NetworkWithBackwardEdges, Signal};
 use rustc_hash::FxHashMap;
 use std::collections::hash_map::Entry;
@@ -35,34 +35,38 @@ impl<'a> Rows<'a> {
     pub fn new(
         ntk: &impl NetworkWithBackwardEdges<Node = Mig>,
         architecture: &'a Architecture,
-    ) -> Self {
+    ) -> Result<Self, CompileError> {
         let mut rows = Rows {
             signals: FxHashMap::default(),
             rows: FxHashMap::default(),
             spill_counter: 0,
             architecture,
         };
-        rows.add_leafs(ntk);
-        rows
+        rows.add_leafs(ntk)?;
+        Ok(rows)
     }
 
-    fn add_leafs(&mut self, ntk: &impl NetworkWithBackwardEdges<Node = Mig>) {
+    fn add_leafs(
+        &mut self,
+        ntk: &impl NetworkWithBackwardEdges<Node = Mig>,
+    ) -> Result<(), CompileError> {
         let leafs = ntk.leafs();
         self.rows.reserve(leafs.size_hint().0);
         for id in leafs {
             let node = ntk.node(id);
             match node {
                 Mig::Input(i) => {
-                    self.set_empty_row_signal(Row::In(i), Signal::new(id, false));
+                    self.set_empty_row_signal(Row::In(i), Signal::new(id, false))?;
                 }
                 Mig::False => {
                     let signal = Signal::new(id, false);
-                    self.set_empty_row_signal(Row::Const(false), signal);
-                    self.set_empty_row_signal(Row::Const(true), signal.invert());
+                    self.set_empty_row_signal(Row::Const(false), signal)?;
+                    self.set_empty_row_signal(Row::Const(true), signal.invert())?;
                 }
-                _ => unreachable!("leaf node should be either an input or a constant"),
+                _ => return Err(CompileError::InvalidLeaf { node: id }),
             };
         }
+        Ok(())
     }
 
     pub fn get_free_dcc(&self) -> Option<u8> {
@@ -103,10 +107,10 @@ impl<'a> Rows<'a> {
     }
 
     /// Adds a new spill row with the given signal and returns its id.
-    pub fn add_spill(&mut self, signal: Signal) -> u32 {
+    pub fn add_spill(&mut self, signal: Signal) -> Result<u32, CompileError> {
         self.spill_counter += 1;
-        self.set_empty_row_signal(Row::Spill(self.spill_counter), signal);
-        self.spill_counter
+        self.set_empty_row_signal(Row::Spill(self.spill_counter), signal)?;
+        Ok(self.spill_counter)
     }
 
     /// Sets the current signal of the given operand. That is, if it is a T-operand, sets the signal
@@ -122,12 +126,14 @@ impl<'a> Rows<'a> {
 
     /// Equivalent to `set_row_signals`, but additionally ensures that the row was previously empty
     /// or contained the same signal.
-    fn set_empty_row_signal(&mut self, row: Row, signal: Signal) {
-        assert_eq!(
-            self.set_row_signal(row, signal),
-            None,
-            "row {row:?} should be empty"
-        )
+    fn set_empty_row_signal(&mut self, row: Row, signal: Signal) -> Result<(), CompileError> {
+        match self.set_row_signal(row, signal) {
+            None => Ok(()),
+            Some(_) => Err(CompileError::RowOccupied {
+                node: signal.node_id(),
+                row,
+            }),
+        }
     }
 
     /// Sets the signal of the given row, updating `self.rows` and `self.signals` accordingly.
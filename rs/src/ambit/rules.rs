@@ -0,0 +1,414 @@
+use super::Architecture;
+use eggmock::egg::{
+    rewrite, Analysis, Applier, EGraph, Id, Language, Pattern, PatternAst, Rewrite, Subst, Symbol,
+    Var,
+};
+use eggmock::MigLanguage;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+
+fn rewrite_rules<A: Analysis<MigLanguage>>() -> Vec<Rewrite<MigLanguage, A>> {
+    let mut rules = vec![
+        rewrite!("commute_1"; "(maj ?a ?b ?c)" => "(maj ?b ?a ?c)"),
+        rewrite!("commute_2"; "(maj ?a ?b ?c)" => "(maj ?a ?c ?b)"),
+        rewrite!("not_not"; "(! (! ?a))" => "?a"),
+        rewrite!("maj_1"; "(maj ?a ?a ?b)" => "?a"),
+        rewrite!("maj_2"; "(maj ?a (! ?a) ?b)" => "?b"),
+        rewrite!("associativity"; "(maj ?a ?b (maj ?c ?b ?d))" => "(maj ?d ?b (maj ?c ?b ?a))"),
+        rewrite!("complementary_associativity"; "(maj ?a ?b (maj ?c (! ?b) ?d))" => "(maj ?a ?b (maj ?c ?a ?d))"),
+    ];
+    rules.extend(rewrite!("invert"; "(! (maj ?a ?b ?c))" <=> "(maj (! ?a) (! ?b) (! ?c))"));
+    rules.extend(rewrite!("distributivity"; "(maj ?a ?b (maj ?c ?d ?e))" <=> "(maj (maj ?a ?b ?c) (maj ?a ?b ?d) ?e)"));
+    rules
+}
+
+/// Built-in rules that are only used if enabled with [RuleSet::enable]. Relevance matches every MAJ
+/// node and substitution every MAJ node with a MAJ child, and both rebuild subterms up to
+/// [SUBSTITUTION_DEPTH], so they grow the e-graph much faster than the other rules.
+fn optional_rewrite_rules<A: Analysis<MigLanguage>>() -> Vec<Rewrite<MigLanguage, A>> {
+    vec![
+        rewrite!("relevance"; "(maj ?a ?b ?c)" => { Relevance::new("?a", "?b", "?c") }),
+        rewrite!("substitution"; "(maj ?a ?b (maj ?v ?u ?c))" => { Substitution::new("?v", "?u") }),
+    ]
+}
+
+/// Additional rules for architectures providing activations of five rows. MAJ5(a, b, c, d, e) is
+/// expressed as `M(e, M(b, c, d), M(a, b, M(a, c, d)))`, which the compiler computes using a single
+/// activation.
+fn maj5_rewrite_rules<A: Analysis<MigLanguage>>() -> Vec<Rewrite<MigLanguage, A>> {
+    let mut rules = rewrite_rules();
+    // XOR3 (e.g. the sum of a full adder) is equal to MAJ5(a, b, c, !M(a, b, c), !M(a, b, c))
+    rules.push(rewrite!("maj5_xor3"; "(maj (! (maj ?a ?b ?c)) ?c (maj ?a ?b (! ?c)))" => "(maj (! (maj ?a ?b ?c)) (maj ?b ?c (! (maj ?a ?b ?c))) (maj ?a ?b (maj ?a ?c (! (maj ?a ?b ?c)))))"));
+    rules
+}
+
+/// How deep [Relevance] and [Substitution] descend into a term to replace a signal. Terms are
+/// rebuilt along one representative node per e-class, so deeper substitutions mostly add nodes
+/// without enabling further rewrites.
+const SUBSTITUTION_DEPTH: usize = 3;
+
+/// Ψ.R: `M(x, y, z) = M(x, y, z[x/!y])`, i.e. `x` may be replaced by `!y` within `z`, since `z` only
+/// matters if `x` and `y` differ.
+struct Relevance {
+    x: Var,
+    y: Var,
+    z: Var,
+}
+
+/// Ψ.S: `f = M(v, M(!v, f[v/u], u), M(!v, f[v/!u], !u))` for the matched term `f`, where `v` and `u`
+/// are taken from the match. The rule `substitution` replaces an operand `v` of a child of `f` by
+/// another operand `u` of the same child.
+struct Substitution {
+    v: Var,
+    u: Var,
+}
+
+impl Relevance {
+    fn new(x: &str, y: &str, z: &str) -> Self {
+        Self {
+            x: x.parse().unwrap(),
+            y: y.parse().unwrap(),
+            z: z.parse().unwrap(),
+        }
+    }
+}
+
+impl Substitution {
+    fn new(v: &str, u: &str) -> Self {
+        Self {
+            v: v.parse().unwrap(),
+            u: u.parse().unwrap(),
+        }
+    }
+}
+
+impl<N: Analysis<MigLanguage>> Applier<MigLanguage, N> for Relevance {
+    fn apply_one(
+        &self,
+        egraph: &mut EGraph<MigLanguage, N>,
+        eclass: Id,
+        subst: &Subst,
+        _searcher_ast: Option<&PatternAst<MigLanguage>>,
+        _rule_name: Symbol,
+    ) -> Vec<Id> {
+        let (x, y, z) = (subst[self.x], subst[self.y], subst[self.z]);
+        let not_y = egraph.add(MigLanguage::Not(y));
+        let z = substitute(egraph, z, x, not_y, SUBSTITUTION_DEPTH);
+        let id = egraph.add(MigLanguage::Maj([x, y, z]));
+        union(egraph, eclass, id)
+    }
+
+    fn vars(&self) -> Vec<Var> {
+        vec![self.x, self.y, self.z]
+    }
+}
+
+impl<N: Analysis<MigLanguage>> Applier<MigLanguage, N> for Substitution {
+    fn apply_one(
+        &self,
+        egraph: &mut EGraph<MigLanguage, N>,
+        eclass: Id,
+        subst: &Subst,
+        _searcher_ast: Option<&PatternAst<MigLanguage>>,
+        _rule_name: Symbol,
+    ) -> Vec<Id> {
+        let (v, u) = (subst[self.v], subst[self.u]);
+        let not_v = egraph.add(MigLanguage::Not(v));
+        let not_u = egraph.add(MigLanguage::Not(u));
+        let with_u = substitute(egraph, eclass, v, u, SUBSTITUTION_DEPTH);
+        let with_not_u = substitute(egraph, eclass, v, not_u, SUBSTITUTION_DEPTH);
+        let a = egraph.add(MigLanguage::Maj([not_v, with_u, u]));
+        let b = egraph.add(MigLanguage::Maj([not_v, with_not_u, not_u]));
+        let id = egraph.add(MigLanguage::Maj([v, a, b]));
+        union(egraph, eclass, id)
+    }
+
+    fn vars(&self) -> Vec<Var> {
+        vec![self.v, self.u]
+    }
+}
+
+/// Returns the e-class of the term of `id` with each occurrence of `from` up to the given depth
+/// replaced by `to`. Each e-class is represented by its first non-leaf node.
+fn substitute<N: Analysis<MigLanguage>>(
+    egraph: &mut EGraph<MigLanguage, N>,
+    id: Id,
+    from: Id,
+    to: Id,
+    depth: usize,
+) -> Id {
+    let id = egraph.find(id);
+    if id == egraph.find(from) {
+        return to;
+    }
+    if depth == 0 {
+        return id;
+    }
+    let Some(node) = egraph[id].iter().find(|node| !node.is_leaf()).cloned() else {
+        return id;
+    };
+    let node = node.map_children(|child| substitute(egraph, child, from, to, depth - 1));
+    egraph.add(node)
+}
+
+fn union<N: Analysis<MigLanguage>>(
+    egraph: &mut EGraph<MigLanguage, N>,
+    eclass: Id,
+    id: Id,
+) -> Vec<Id> {
+    if egraph.union(eclass, id) {
+        vec![eclass]
+    } else {
+        vec![]
+    }
+}
+
+/// The rewrite rules used for equality saturation, identified by their names. A bidirectional rule
+/// `name` consists of the two rules `name` and `name-rev`.
+#[derive(Debug, Clone)]
+pub struct RuleSet<A: Analysis<MigLanguage>> {
+    rules: Vec<Rewrite<MigLanguage, A>>,
+}
+
+#[derive(Debug)]
+pub enum RuleSetError {
+    Io(PathBuf, std::io::Error),
+    /// The rule in the given line (1-based) of the file is invalid.
+    Parse {
+        path: PathBuf,
+        line: usize,
+        reason: String,
+    },
+    DuplicateRule(String),
+    UnknownRule(String),
+}
+
+impl<A: Analysis<MigLanguage>> RuleSet<A> {
+    /// Returns the built-in rules for the given architecture.
+    pub fn builtin(architecture: &Architecture) -> Self {
+        let rules = if architecture.max_maj_operands() >= 5 {
+            maj5_rewrite_rules()
+        } else {
+            rewrite_rules()
+        };
+        Self { rules }
+    }
+
+    pub fn rules(&self) -> &[Rewrite<MigLanguage, A>] {
+        &self.rules
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.rules.iter().map(|rule| rule.name.as_str())
+    }
+
+    /// Adds the rules of the given file. Each line contains a rule in egg's pattern syntax, either
+    /// `name: lhs => rhs` or `name: lhs <=> rhs` for bidirectional rules, e.g.
+    ///
+    /// ```text
+    /// # text after a `#` is ignored
+    /// commute_3: (maj ?a ?b ?c) => (maj ?c ?b ?a)
+    /// ```
+    pub fn load(&mut self, path: impl AsRef<Path>) -> Result<(), RuleSetError> {
+        let path = path.as_ref();
+        let source =
+            std::fs::read_to_string(path).map_err(|err| RuleSetError::Io(path.into(), err))?;
+        for (idx, line) in source.lines().enumerate() {
+            let line = line
+                .split_once('#')
+                .map_or(line, |(content, _)| content)
+                .trim();
+            if line.is_empty() {
+                continue;
+            }
+            let rules = parse_rule(line).map_err(|reason| RuleSetError::Parse {
+                path: path.into(),
+                line: idx + 1,
+                reason,
+            })?;
+            for rule in rules {
+                if self.names().any(|name| name == rule.name.as_str()) {
+                    return Err(RuleSetError::DuplicateRule(rule.name.to_string()));
+                }
+                self.rules.push(rule);
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the optional built-in rule with the given name (see [optional_rewrite_rules]) unless it
+    /// is part of the rules already.
+    pub fn enable(&mut self, name: &str) -> Result<(), RuleSetError> {
+        if self.names().any(|existing| existing == name) {
+            return Ok(());
+        }
+        let rule = optional_rewrite_rules()
+            .into_iter()
+            .find(|rule| rule.name.as_str() == name)
+            .ok_or_else(|| RuleSetError::UnknownRule(name.to_string()))?;
+        self.rules.push(rule);
+        Ok(())
+    }
+
+    /// Removes the rule with the given name, including both directions of a bidirectional rule.
+    pub fn disable(&mut self, name: &str) -> Result<(), RuleSetError> {
+        let reverse = format!("{name}-rev");
+        let len = self.rules.len();
+        self.rules
+            .retain(|rule| rule.name.as_str() != name && rule.name.as_str() != reverse);
+        if self.rules.len() == len {
+            return Err(RuleSetError::UnknownRule(name.to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Parses a single rule in the syntax described in [RuleSet::load].
+fn parse_rule<A: Analysis<MigLanguage>>(
+    line: &str,
+) -> Result<Vec<Rewrite<MigLanguage, A>>, String> {
+    let (name, rule) = line.split_once(':').ok_or("expected `name: lhs => rhs`")?;
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("missing rule name".to_string());
+    }
+    let (rule, bidirectional) = match rule.split_once("<=>") {
+        Some(rule) => (rule, true),
+        None => (
+            rule.split_once("=>").ok_or("expected `=>` or `<=>`")?,
+            false,
+        ),
+    };
+    let parse = |pattern: &str| {
+        pattern
+            .trim()
+            .parse::<Pattern<MigLanguage>>()
+            .map_err(|err| format!("invalid pattern `{}`: {err}", pattern.trim()))
+    };
+    let (lhs, rhs) = (parse(rule.0)?, parse(rule.1)?);
+    let reverse = bidirectional
+        .then(|| Rewrite::new(format!("{name}-rev"), rhs.clone(), lhs.clone()))
+        .transpose()?;
+    let forward = Rewrite::new(name, lhs, rhs)?;
+    Ok(std::iter::once(forward).chain(reverse).collect())
+}
+
+impl Display for RuleSetError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(path, err) => write!(f, "could not read {}: {err}", path.display()),
+            Self::Parse { path, line, reason } => {
+                write!(
+                    f,
+                    "invalid rule in {}, line {line}: {reason}",
+                    path.display()
+                )
+            }
+            Self::DuplicateRule(name) => write!(f, "there is more than one rule named {name}"),
+            Self::UnknownRule(name) => write!(f, "there is no rule named {name}"),
+        }
+    }
+}
+
+impl std::error::Error for RuleSetError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eggmock::egg::Runner;
+
+    /// Applies the built-in or optional rule with the given name once to the e-graph.
+    fn run_rule(egraph: EGraph<MigLanguage, ()>, name: &str) -> EGraph<MigLanguage, ()> {
+        let rule = rewrite_rules()
+            .into_iter()
+            .chain(optional_rewrite_rules())
+            .find(|rule| rule.name.as_str() == name)
+            .unwrap();
+        Runner::default()
+            .with_egraph(egraph)
+            .with_iter_limit(1)
+            .run([&rule])
+            .egraph
+    }
+
+    /// Returns the e-class of the given node, whose children have to be in the e-graph.
+    fn class(egraph: &EGraph<MigLanguage, ()>, node: MigLanguage) -> Id {
+        egraph
+            .lookup(node)
+            .expect("rewritten term should be in the e-graph")
+    }
+
+    fn inputs<const N: usize>(egraph: &mut EGraph<MigLanguage, ()>) -> [Id; N] {
+        std::array::from_fn(|i| egraph.add(MigLanguage::Input(i as u64)))
+    }
+
+    #[test]
+    fn complementary_associativity() {
+        // M(a, b, M(c, !b, d)) = M(a, b, M(c, a, d))
+        let mut egraph = EGraph::default();
+        let [a, b, c, d] = inputs(&mut egraph);
+        let not_b = egraph.add(MigLanguage::Not(b));
+        let inner = egraph.add(MigLanguage::Maj([c, not_b, d]));
+        let root = egraph.add(MigLanguage::Maj([a, b, inner]));
+
+        let egraph = run_rule(egraph, "complementary_associativity");
+        let inner = class(&egraph, MigLanguage::Maj([c, a, d]));
+        let rewritten = class(&egraph, MigLanguage::Maj([a, b, inner]));
+        assert_eq!(egraph.find(rewritten), egraph.find(root));
+    }
+
+    #[test]
+    fn relevance() {
+        // M(x, y, M(x, c, d)) = M(x, y, M(!y, c, d))
+        let mut egraph = EGraph::default();
+        let [x, y, c, d] = inputs(&mut egraph);
+        let z = egraph.add(MigLanguage::Maj([x, c, d]));
+        let root = egraph.add(MigLanguage::Maj([x, y, z]));
+
+        let egraph = run_rule(egraph, "relevance");
+        let not_y = class(&egraph, MigLanguage::Not(y));
+        let z = class(&egraph, MigLanguage::Maj([not_y, c, d]));
+        let rewritten = class(&egraph, MigLanguage::Maj([x, y, z]));
+        assert_eq!(egraph.find(rewritten), egraph.find(root));
+    }
+
+    #[test]
+    fn substitution() {
+        // f = M(a, b, M(v, u, c)) = M(v, M(!v, f[v/u], u), M(!v, f[v/!u], !u))
+        let mut egraph = EGraph::default();
+        let [a, b, v, u, c] = inputs(&mut egraph);
+        let inner = egraph.add(MigLanguage::Maj([v, u, c]));
+        let root = egraph.add(MigLanguage::Maj([a, b, inner]));
+
+        let egraph = run_rule(egraph, "substitution");
+        let not_v = class(&egraph, MigLanguage::Not(v));
+        let not_u = class(&egraph, MigLanguage::Not(u));
+        let with_u = {
+            let inner = class(&egraph, MigLanguage::Maj([u, u, c]));
+            class(&egraph, MigLanguage::Maj([a, b, inner]))
+        };
+        let with_not_u = {
+            let inner = class(&egraph, MigLanguage::Maj([not_u, u, c]));
+            class(&egraph, MigLanguage::Maj([a, b, inner]))
+        };
+        let first = class(&egraph, MigLanguage::Maj([not_v, with_u, u]));
+        let second = class(&egraph, MigLanguage::Maj([not_v, with_not_u, not_u]));
+        let rewritten = class(&egraph, MigLanguage::Maj([v, first, second]));
+        assert_eq!(egraph.find(rewritten), egraph.find(root));
+    }
+
+    #[test]
+    fn optional_rules_are_opt_in() {
+        let mut rules = RuleSet::<()>::builtin(&crate::ambit::presets::AMBIT);
+        assert!(!rules
+            .names()
+            .any(|name| name == "relevance" || name == "substitution"));
+        rules.enable("relevance").unwrap();
+        rules.enable("relevance").unwrap();
+        assert_eq!(rules.names().filter(|name| *name == "relevance").count(), 1);
+        assert!(matches!(
+            rules.enable("unknown"),
+            Err(RuleSetError::UnknownRule(_))
+        ));
+    }
+}
@@ -0,0 +1,265 @@
+use super::{Address, BitwiseAddress, Instruction, Program, Row, SingleRowAddress};
+use rustc_hash::FxHashMap;
+use smallvec::SmallVec;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+/// The final contents of the output rows of a simulated program by their index.
+pub type SimulationOutputs = BTreeMap<u64, Vec<u64>>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SimulationError {
+    /// Not all inputs consist of the same number of lanes.
+    LaneMismatch { input: usize },
+    /// The instruction reads a row that has not been written before.
+    UninitializedRow { instruction: usize, row: Row },
+    /// The instruction writes to a row that can only be read from.
+    ReadOnlyRow { instruction: usize, row: Row },
+    /// The instruction activates an even number of rows that do not agree on all bits, so the
+    /// result is not defined.
+    UndefinedActivation { instruction: usize },
+}
+
+/// The contents of all rows during a simulation, where each row consists of the same number of
+/// 64-bit lanes.
+struct SimulationState<'i> {
+    inputs: &'i [Vec<u64>],
+    rows: FxHashMap<Row, Vec<u64>>,
+    lanes: usize,
+}
+
+impl Program<'_> {
+    /// Executes this program on the given inputs, where `inputs[i]` is the content of `Row::In(i)`.
+    /// Each row is simulated as a bit-vector of the same number of lanes as the inputs, or of a
+    /// single lane without inputs, i.e. each bit is an independent execution of the program.
+    pub fn simulate(&self, inputs: &[Vec<u64>]) -> Result<SimulationOutputs, SimulationError> {
+        let lanes = inputs.first().map_or(1, Vec::len);
+        if let Some(input) = inputs.iter().position(|input| input.len() != lanes) {
+            return Err(SimulationError::LaneMismatch { input });
+        }
+        let mut state = SimulationState {
+            inputs,
+            rows: FxHashMap::default(),
+            lanes,
+        };
+        for (idx, instruction) in self.instructions.iter().enumerate() {
+            match *instruction {
+                Instruction::AP(address) => {
+                    self.activate(&mut state, idx, address)?;
+                }
+                Instruction::AAP(from, to) => {
+                    let value = self.activate(&mut state, idx, from)?;
+                    for address in to.row_addresses(self.architecture) {
+                        state.write(idx, address, &value)?;
+                    }
+                }
+            }
+        }
+        Ok(state
+            .rows
+            .into_iter()
+            .filter_map(|(row, value)| match row {
+                Row::Out(i) => Some((i, value)),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// Activates the rows of the given address and returns the resulting value, i.e. the value of
+    /// the row for single-row addresses or the majority of all activated rows otherwise, which is
+    /// also written back to all of them.
+    fn activate(
+        &self,
+        state: &mut SimulationState,
+        instruction: usize,
+        address: Address,
+    ) -> Result<Vec<u64>, SimulationError> {
+        let Address::Bitwise(BitwiseAddress::Multiple(_)) = address else {
+            let address = address
+                .as_single_row()
+                .expect("address should refer to a single row");
+            return state.read(instruction, address);
+        };
+        let values = address
+            .row_addresses(self.architecture)
+            .map(|address| state.read(instruction, address))
+            .collect::<Result<Vec<_>, _>>()?;
+        let value = (0..state.lanes)
+            .map(|lane| {
+                let lane_values: SmallVec<[u64; 5]> =
+                    values.iter().map(|value| value[lane]).collect();
+                majority(&lane_values).ok_or(SimulationError::UndefinedActivation { instruction })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        for address in address.row_addresses(self.architecture) {
+            state.write(instruction, address, &value)?;
+        }
+        Ok(value)
+    }
+}
+
+impl SimulationState<'_> {
+    fn read(
+        &self,
+        instruction: usize,
+        address: SingleRowAddress,
+    ) -> Result<Vec<u64>, SimulationError> {
+        let row = address.row();
+        let value = match row {
+            Row::In(i) => self.inputs.get(i as usize).cloned(),
+            Row::Const(value) => Some(vec![if value { !0 } else { 0 }; self.lanes]),
+            _ => self.rows.get(&row).cloned(),
+        };
+        let mut value = value.ok_or(SimulationError::UninitializedRow { instruction, row })?;
+        if address.inverted() {
+            value.iter_mut().for_each(|lane| *lane = !*lane);
+        }
+        Ok(value)
+    }
+
+    fn write(
+        &mut self,
+        instruction: usize,
+        address: SingleRowAddress,
+        value: &[u64],
+    ) -> Result<(), SimulationError> {
+        let row = address.row();
+        if matches!(row, Row::In(_) | Row::Const(_)) {
+            return Err(SimulationError::ReadOnlyRow { instruction, row });
+        }
+        let value = value
+            .iter()
+            .map(|lane| if address.inverted() { !lane } else { *lane })
+            .collect();
+        self.rows.insert(row, value);
+        Ok(())
+    }
+}
+
+/// Returns the bitwise majority of the given values or [None] if they are tied on any bit, which
+/// is only possible for an even number of values.
+fn majority(values: &[u64]) -> Option<u64> {
+    match values {
+        [a] => Some(*a),
+        [a, b, c] => Some((a & b) | (a & c) | (b & c)),
+        _ => {
+            let mut result = 0;
+            for bit in 0..u64::BITS {
+                let ones = values.iter().filter(|v| (*v >> bit) & 1 == 1).count();
+                if ones * 2 == values.len() {
+                    return None;
+                }
+                if ones * 2 > values.len() {
+                    result |= 1 << bit;
+                }
+            }
+            Some(result)
+        }
+    }
+}
+
+impl Display for SimulationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::LaneMismatch { input } => {
+                write!(f, "input {input} has a different number of lanes")
+            }
+            Self::UninitializedRow { instruction, row } => write!(
+                f,
+                "instruction {instruction} reads {row:?}, which has not been written"
+            ),
+            Self::ReadOnlyRow { instruction, row } => {
+                write!(
+                    f,
+                    "instruction {instruction} writes to read-only row {row:?}"
+                )
+            }
+            Self::UndefinedActivation { instruction } => write!(
+                f,
+                "instruction {instruction} activates an even number of rows with differing values"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SimulationError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambit::presets::AMBIT;
+
+    fn simulate(source: &str, inputs: &[Vec<u64>]) -> Result<SimulationOutputs, SimulationError> {
+        Program::parse(&AMBIT, source).unwrap().simulate(inputs)
+    }
+
+    #[test]
+    fn majority_per_lane() {
+        let inputs = [
+            vec![0b1100, 0xFFFF_0000],
+            vec![0b1010, 0x00FF_FF00],
+            vec![0b0110, 0x0000_FFFF],
+        ];
+        let outputs = simulate(
+            "AAP I0 T0\nAAP I1 T1\nAAP I2 T2\nAP [T0, T1, T2]\nAAP T0 O0\n",
+            &inputs,
+        )
+        .unwrap();
+        assert_eq!(outputs[&0], vec![0b1110, 0x00FF_FF00]);
+    }
+
+    #[test]
+    fn dcc_inversion() {
+        let inputs = [vec![0b1100, 0], vec![0b1010, !0]];
+        let outputs = simulate(
+            "\
+AAP I0 DCC0
+AAP ~DCC0 O0
+AAP I1 [~DCC1, T1]
+AAP DCC1 O1
+AAP T1 O2
+AAP I0 T0
+AAP I0 T3
+AP [DCC1, T0, T3]
+AAP DCC1 O3
+",
+            &inputs,
+        )
+        .unwrap();
+        assert_eq!(outputs[&0], vec![!0b1100, !0]);
+        assert_eq!(outputs[&1], vec![!0b1010, 0]);
+        assert_eq!(outputs[&2], vec![0b1010, !0]);
+        // the majority of !I1 and I0 twice
+        assert_eq!(outputs[&3], vec![0b1100, 0]);
+    }
+
+    #[test]
+    fn constant_rows() {
+        let inputs = [vec![0b1100, 0b1010]];
+        let outputs = simulate("AAP C0 O0\nAAP C1 O1\n", &inputs).unwrap();
+        assert_eq!(outputs[&0], vec![0, 0]);
+        assert_eq!(outputs[&1], vec![!0, !0]);
+
+        // without inputs, the constants are still simulated on a single lane
+        let outputs = simulate("AAP C0 O0\nAAP C1 O1\n", &[]).unwrap();
+        assert_eq!(outputs[&0], vec![0]);
+        assert_eq!(outputs[&1], vec![!0]);
+
+        assert_eq!(
+            simulate("AAP I0 C1\n", &inputs),
+            Err(SimulationError::ReadOnlyRow {
+                instruction: 0,
+                row: Row::Const(true)
+            })
+        );
+    }
+
+    #[test]
+    fn lane_mismatch() {
+        let inputs = [vec![0, 0], vec![0], vec![0, 0]];
+        assert_eq!(
+            simulate("AAP I0 O0\n", &inputs),
+            Err(SimulationError::LaneMismatch { input: 1 })
+        );
+    }
+}
@@ -0,0 +1,237 @@
+//! Lowers physically addressed programs to the DRAM commands issued to execute them. Each `AAP`
+//! activates the rows of its first and then of its second address and precharges the bank
+//! afterward, each `AP` only activates the rows of its address before precharging. Commands are
+//! timed according to the cost model of the architecture.
+//!
+//! Traces are written in DRAMPower's command trace format, i.e. one `cycle,command,bank` line per
+//! command, so that they can be passed to DRAMPower as they are. As this format has no row
+//! addresses, the rows are written to a separate row map with one `cycle,bank,subarray,row,rows`
+//! line per activation, where `row` is the row address within the bank and `rows` the number of
+//! simultaneously activated rows. For example, `AAP I0 T0` in subarray 3 of bank 2 becomes the
+//! trace
+//!
+//! ```text
+//! 0,ACT,2
+//! 28,ACT,2
+//! 56,PRE,2
+//! ```
+//!
+//! with the row map
+//!
+//! ```text
+//! 0,2,3,3090,1
+//! 28,2,3,3072,1
+//! ```
+//!
+//! The back-to-back activations of an `AAP` are what copies the first row to the second one, so
+//! they cannot be lowered to timing-compliant command sequences. Ramulator and DRAMSim3 take traces
+//! of memory requests rather than commands and schedule the commands themselves, so they cannot
+//! replay such a trace without changes to their memory controllers. DRAMPower replays commands as
+//! given without a memory controller and thus accepts the back-to-back activations, but only warns
+//! about the second one and does not count its energy. The row map keeps the rows and their number,
+//! which determine the actual cost of each activation, available to tools that account for it.
+
+use super::{
+    b_group_address, Address, AllocationError, Architecture, PhysicalAddress, PhysicalInstruction,
+    PhysicalProgram,
+};
+use std::fmt::{Display, Formatter};
+use std::io::{self, Write};
+
+/// Where and at which clock the commands of a trace are issued.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TraceSettings {
+    pub bank: u32,
+    pub subarray: u32,
+    /// The clock period (ns), i.e. tCK.
+    pub t_ck: f64,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Command {
+    /// Activates a row, where `row` is the address within the bank and `rows` the number of rows
+    /// the B-group decoder activates simultaneously for it.
+    Activate {
+        row: u32,
+        rows: usize,
+    },
+    Precharge,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TraceCommand {
+    pub cycle: u64,
+    pub bank: u32,
+    pub subarray: u32,
+    pub command: Command,
+}
+
+impl Default for TraceSettings {
+    /// The first subarray of the first bank of a DDR3-1600 device.
+    fn default() -> Self {
+        Self {
+            bank: 0,
+            subarray: 0,
+            t_ck: 1.25,
+        }
+    }
+}
+
+impl PhysicalProgram<'_> {
+    /// Returns the commands executing this program. Each command is issued at the first cycle at
+    /// which the previous one has completed according to the cost model of the architecture. Fails
+    /// if an address refers to a bitwise operand that is not part of the architecture.
+    pub fn commands(&self, settings: &TraceSettings) -> Result<Vec<TraceCommand>, AllocationError> {
+        let cost_model = &self.architecture.cost_model;
+        let first_row = settings.subarray * self.subarray.rows;
+        let mut commands = Vec::new();
+        let mut time = 0.0;
+        let mut issue = |command, latency: f64| {
+            commands.push(TraceCommand {
+                cycle: (time / settings.t_ck).ceil() as u64,
+                bank: settings.bank,
+                subarray: settings.subarray,
+                command,
+            });
+            time += latency;
+        };
+        for instruction in &self.instructions {
+            let addresses = match *instruction {
+                PhysicalInstruction::AAP(from, to) => [Some(from), Some(to)],
+                PhysicalInstruction::AP(address) => [Some(address), None],
+            };
+            for address in addresses.into_iter().flatten() {
+                let row = first_row + row_address(self.architecture, address)?;
+                let rows = activated_rows(self.architecture, address);
+                issue(
+                    Command::Activate { row, rows },
+                    cost_model.activation_latency(rows),
+                );
+            }
+            issue(Command::Precharge, cost_model.t_rp);
+        }
+        Ok(commands)
+    }
+
+    /// Writes the commands executing this program as a DRAMPower command trace, see the
+    /// [module documentation](self).
+    pub fn write_trace(&self, settings: &TraceSettings, mut writer: impl Write) -> io::Result<()> {
+        let commands = self.commands(settings).map_err(io::Error::other)?;
+        for command in commands {
+            writeln!(writer, "{command}")?;
+        }
+        writer.flush()
+    }
+
+    /// Writes the rows activated by the commands of [PhysicalProgram::write_trace] in the format
+    /// described in the [module documentation](self).
+    pub fn write_row_map(
+        &self,
+        settings: &TraceSettings,
+        mut writer: impl Write,
+    ) -> io::Result<()> {
+        let commands = self.commands(settings).map_err(io::Error::other)?;
+        for command in commands {
+            if let Command::Activate { row, rows } = command.command {
+                writeln!(
+                    writer,
+                    "{},{},{},{row},{rows}",
+                    command.cycle, command.bank, command.subarray
+                )?;
+            }
+        }
+        writer.flush()
+    }
+}
+
+/// Returns the row address of the given address within its subarray.
+fn row_address(
+    architecture: &Architecture,
+    address: PhysicalAddress,
+) -> Result<u32, AllocationError> {
+    match address {
+        PhysicalAddress::Row(row) => Ok(row),
+        PhysicalAddress::Bitwise(address) => b_group_address(architecture, address),
+    }
+}
+
+/// Returns the number of rows the given address activates simultaneously.
+fn activated_rows(architecture: &Architecture, address: PhysicalAddress) -> usize {
+    match address {
+        PhysicalAddress::Row(_) => 1,
+        PhysicalAddress::Bitwise(address) => Address::Bitwise(address)
+            .row_addresses(architecture)
+            .count(),
+    }
+}
+
+impl Display for TraceCommand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let command = match self.command {
+            Command::Activate { .. } => "ACT",
+            Command::Precharge => "PRE",
+        };
+        write!(f, "{},{command},{}", self.cycle, self.bank)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ambit::presets::AMBIT;
+    use crate::ambit::{Program, Subarray};
+
+    #[test]
+    fn golden_trace() {
+        let program = Program::parse(
+            &AMBIT,
+            "AAP I0 T0\nAAP I1 T1\nAAP I2 T2\nAP [T0, T1, T2]\nAAP T0 O0\n",
+        )
+        .unwrap();
+        let physical = program.allocate_rows(&Subarray::default()).unwrap();
+        let settings = TraceSettings {
+            bank: 2,
+            subarray: 3,
+            ..TraceSettings::default()
+        };
+
+        let mut trace = Vec::new();
+        physical.write_trace(&settings, &mut trace).unwrap();
+        assert_eq!(
+            String::from_utf8(trace).unwrap(),
+            "\
+0,ACT,2
+28,ACT,2
+56,PRE,2
+67,ACT,2
+95,ACT,2
+123,PRE,2
+134,ACT,2
+162,ACT,2
+190,PRE,2
+201,ACT,2
+233,PRE,2
+244,ACT,2
+272,ACT,2
+300,PRE,2
+"
+        );
+
+        let mut row_map = Vec::new();
+        physical.write_row_map(&settings, &mut row_map).unwrap();
+        assert_eq!(
+            String::from_utf8(row_map).unwrap(),
+            "\
+0,2,3,3090,1
+28,2,3,3072,1
+67,2,3,3091,1
+95,2,3,3073,1
+134,2,3,3092,1
+162,2,3,3074,1
+201,2,3,3084,3
+244,2,3,3072,1
+272,2,3,3093,1
+"
+        );
+    }
+}
@@ -0,0 +1,367 @@
+use super::{Program, SimulationError, SimulationOutputs};
+use eggmock::egg::{Analysis, AstSize, EGraph, Extractor, Id};
+use eggmock::{Mig, MigLanguage, Network, Node, Signal};
+use rustc_hash::FxHashMap;
+use std::fmt::{Display, Formatter};
+
+/// Networks with at most this many inputs are verified on all input patterns.
+pub const MAX_EXHAUSTIVE_INPUTS: usize = 12;
+/// Number of 64-bit lanes of random patterns used for networks with more inputs.
+const RANDOM_LANES: usize = 64;
+
+/// The two networks whose outputs differ.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum VerificationStage {
+    /// The extracted network differs from the network given to the compiler.
+    Extraction,
+    /// The compiled program differs from the extracted network.
+    Program,
+}
+
+/// An output that differs for the given input pattern, where `pattern[i]` is the value of input
+/// `i`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub stage: VerificationStage,
+    pub output: usize,
+    pub pattern: Vec<bool>,
+    pub expected: bool,
+    pub actual: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerificationError {
+    Simulation(SimulationError),
+    /// Contains the first mismatch found for each mismatching output.
+    Mismatches(Vec<Mismatch>),
+}
+
+/// Checks that the extracted network and the compiled program compute the same function as the
+/// network given to the compiler, i.e. the e-graph it was received into before rewriting. This
+/// e-graph should not have an analysis, since classes merged by an analysis are simulated using
+/// their smallest node and thus not verified.
+pub fn verify<A: Analysis<MigLanguage>>(
+    input: &EGraph<MigLanguage, A>,
+    outputs: &[Id],
+    extracted: &impl Network<Node = Mig>,
+    program: &Program,
+) -> Result<(), VerificationError> {
+    let (patterns, expected) = simulate_input(input, outputs);
+    let extracted = simulate_network(extracted, &patterns);
+    let compiled = program
+        .simulate(&patterns)
+        .map_err(VerificationError::Simulation)?;
+
+    let mut mismatches = Vec::new();
+    for (output, expected) in expected.iter().enumerate() {
+        let extracted = &extracted[output];
+        if let Some(mismatch) = mismatch(
+            &patterns,
+            VerificationStage::Extraction,
+            output,
+            expected,
+            extracted,
+        ) {
+            mismatches.push(mismatch);
+            continue;
+        }
+        mismatches.extend(program_mismatch(&patterns, &compiled, output, extracted));
+    }
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(VerificationError::Mismatches(mismatches))
+    }
+}
+
+/// Checks that the given program computes the same function as the given network, e.g. a
+/// reference program loaded with [Program::parse].
+pub fn verify_program<A: Analysis<MigLanguage>>(
+    input: &EGraph<MigLanguage, A>,
+    outputs: &[Id],
+    program: &Program,
+) -> Result<(), VerificationError> {
+    let (patterns, expected) = simulate_input(input, outputs);
+    let compiled = program
+        .simulate(&patterns)
+        .map_err(VerificationError::Simulation)?;
+    let mismatches: Vec<Mismatch> = expected
+        .iter()
+        .enumerate()
+        .filter_map(|(output, expected)| program_mismatch(&patterns, &compiled, output, expected))
+        .collect();
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(VerificationError::Mismatches(mismatches))
+    }
+}
+
+/// Returns the input patterns for the given e-graph and the values of its outputs, where classes
+/// are simulated using their smallest node.
+fn simulate_input<A: Analysis<MigLanguage>>(
+    input: &EGraph<MigLanguage, A>,
+    outputs: &[Id],
+) -> (Vec<Vec<u64>>, Vec<Vec<u64>>) {
+    let num_inputs = input
+        .classes()
+        .flat_map(|class| &class.nodes)
+        .filter_map(|node| match node {
+            MigLanguage::Input(i) => Some(*i as usize + 1),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0);
+    let patterns = patterns(num_inputs);
+
+    let lanes = patterns.first().map_or(1, Vec::len);
+    let extractor = Extractor::new(input, AstSize);
+    let expected = simulate_classes(
+        input,
+        outputs,
+        lanes,
+        |id| extractor.find_best_node(id),
+        |i| patterns[i as usize].clone(),
+    );
+    (patterns, expected)
+}
+
+/// Returns the first mismatch between the expected and the actual values of the given output.
+fn mismatch(
+    patterns: &[Vec<u64>],
+    stage: VerificationStage,
+    output: usize,
+    expected: &[u64],
+    actual: &[u64],
+) -> Option<Mismatch> {
+    let (pattern, expected) = find_mismatch(patterns, expected, actual)?;
+    Some(Mismatch {
+        stage,
+        output,
+        pattern,
+        expected,
+        actual: !expected,
+    })
+}
+
+/// Returns the first mismatch between the expected values of the given output and those computed
+/// by the program. A missing output of the program is reported as mismatching in all bits.
+fn program_mismatch(
+    patterns: &[Vec<u64>],
+    compiled: &SimulationOutputs,
+    output: usize,
+    expected: &[u64],
+) -> Option<Mismatch> {
+    let inverted = expected.iter().map(|lane| !lane).collect();
+    let compiled = compiled.get(&(output as u64)).unwrap_or(&inverted);
+    mismatch(
+        patterns,
+        VerificationStage::Program,
+        output,
+        expected,
+        compiled,
+    )
+}
+
+/// Returns the values of the inputs for all patterns if there are few enough inputs or for random
+/// patterns otherwise.
+pub fn patterns(num_inputs: usize) -> Vec<Vec<u64>> {
+    // the patterns of the first six inputs within a lane when enumerating all patterns
+    const LANE_PATTERNS: [u64; 6] = [
+        0xAAAA_AAAA_AAAA_AAAA,
+        0xCCCC_CCCC_CCCC_CCCC,
+        0xF0F0_F0F0_F0F0_F0F0,
+        0xFF00_FF00_FF00_FF00,
+        0xFFFF_0000_FFFF_0000,
+        0xFFFF_FFFF_0000_0000,
+    ];
+    if num_inputs <= MAX_EXHAUSTIVE_INPUTS {
+        let lanes = (1usize << num_inputs).div_ceil(64);
+        (0..num_inputs)
+            .map(|input| {
+                (0..lanes)
+                    .map(|lane| match LANE_PATTERNS.get(input) {
+                        Some(pattern) => *pattern,
+                        None if (lane >> (input - 6)) & 1 == 1 => !0,
+                        None => 0,
+                    })
+                    .collect()
+            })
+            .collect()
+    } else {
+        // xorshift, seeded deterministically so that failures are reproducible
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        (0..num_inputs)
+            .map(|_| (0..RANDOM_LANES).map(|_| next()).collect())
+            .collect()
+    }
+}
+
+/// Returns the input pattern of the first lane bit where the values differ together with the
+/// expected value at that bit.
+fn find_mismatch(
+    patterns: &[Vec<u64>],
+    expected: &[u64],
+    actual: &[u64],
+) -> Option<(Vec<bool>, bool)> {
+    let (lane, diff) = expected
+        .iter()
+        .zip(actual)
+        .map(|(expected, actual)| expected ^ actual)
+        .enumerate()
+        .find(|(_, diff)| *diff != 0)?;
+    let bit = diff.trailing_zeros();
+    let pattern = patterns
+        .iter()
+        .map(|input| (input[lane] >> bit) & 1 == 1)
+        .collect();
+    Some((pattern, (expected[lane] >> bit) & 1 == 1))
+}
+
+/// Simulates the given classes of the e-graph, evaluating each class using the node returned by
+/// `node`, which must not lead to cycles. `input` returns the values of the given input.
+pub fn simulate_classes<'g, A: Analysis<MigLanguage>>(
+    graph: &'g EGraph<MigLanguage, A>,
+    outputs: &[Id],
+    lanes: usize,
+    node: impl Fn(Id) -> &'g MigLanguage,
+    input: impl Fn(u64) -> Vec<u64>,
+) -> Vec<Vec<u64>> {
+    let mut values: FxHashMap<Id, Vec<u64>> = FxHashMap::default();
+    let mut stack: Vec<Id> = outputs.iter().map(|id| graph.find(*id)).collect();
+    while let Some(&id) = stack.last() {
+        if values.contains_key(&id) {
+            stack.pop();
+            continue;
+        }
+        let node = node(id);
+        let children: Vec<Id> = match node {
+            MigLanguage::Not(child) => vec![graph.find(*child)],
+            MigLanguage::Maj(children) => children.iter().map(|c| graph.find(*c)).collect(),
+            _ => Vec::new(),
+        };
+        let missing: Vec<Id> = children
+            .iter()
+            .copied()
+            .filter(|child| !values.contains_key(child))
+            .collect();
+        if !missing.is_empty() {
+            stack.extend(missing);
+            continue;
+        }
+        let value = match node {
+            MigLanguage::False => vec![0; lanes],
+            MigLanguage::Input(i) => input(*i),
+            MigLanguage::Not(_) => values[&children[0]].iter().map(|v| !v).collect(),
+            MigLanguage::Maj(_) => {
+                let [a, b, c] = [0, 1, 2].map(|i| &values[&children[i]]);
+                maj_lanes(a, b, c)
+            }
+        };
+        values.insert(id, value);
+        stack.pop();
+    }
+    outputs
+        .iter()
+        .map(|id| values[&graph.find(*id)].clone())
+        .collect()
+}
+
+fn simulate_network(ntk: &impl Network<Node = Mig>, patterns: &[Vec<u64>]) -> Vec<Vec<u64>> {
+    let lanes = patterns.first().map_or(1, Vec::len);
+    let mut values: FxHashMap<eggmock::Id, Vec<u64>> = FxHashMap::default();
+    let signal_value = |values: &FxHashMap<eggmock::Id, Vec<u64>>, signal: Signal| -> Vec<u64> {
+        let value = &values[&signal.node_id()];
+        if signal.is_inverted() {
+            value.iter().map(|v| !v).collect()
+        } else {
+            value.clone()
+        }
+    };
+    let mut stack: Vec<eggmock::Id> = ntk.outputs().map(|signal| signal.node_id()).collect();
+    while let Some(&id) = stack.last() {
+        if values.contains_key(&id) {
+            stack.pop();
+            continue;
+        }
+        let node = ntk.node(id);
+        let missing: Vec<eggmock::Id> = node
+            .inputs()
+            .iter()
+            .map(|signal| signal.node_id())
+            .filter(|child| !values.contains_key(child))
+            .collect();
+        if !missing.is_empty() {
+            stack.extend(missing);
+            continue;
+        }
+        let value = match node {
+            Mig::False => vec![0; lanes],
+            Mig::Input(i) => patterns[i as usize].clone(),
+            Mig::Maj(signals) => {
+                let [a, b, c] = signals.map(|signal| signal_value(&values, signal));
+                maj_lanes(&a, &b, &c)
+            }
+        };
+        values.insert(id, value);
+        stack.pop();
+    }
+    ntk.outputs()
+        .map(|signal| signal_value(&values, signal))
+        .collect()
+}
+
+fn maj_lanes(a: &[u64], b: &[u64], c: &[u64]) -> Vec<u64> {
+    a.iter()
+        .zip(b)
+        .zip(c)
+        .map(|((a, b), c)| (a & b) | (a & c) | (b & c))
+        .collect()
+}
+
+impl Display for VerificationStage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Extraction => write!(f, "extracted network"),
+            Self::Program => write!(f, "compiled program"),
+        }
+    }
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let pattern: String = self
+            .pattern
+            .iter()
+            .map(|value| if *value { '1' } else { '0' })
+            .collect();
+        write!(
+            f,
+            "output {} of the {} is {} instead of {} for inputs {pattern} (input 0 first)",
+            self.output, self.stage, self.actual as u8, self.expected as u8
+        )
+    }
+}
+
+impl Display for VerificationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Simulation(err) => write!(f, "could not simulate program: {err}"),
+            Self::Mismatches(mismatches) => {
+                write!(f, "verification failed:")?;
+                for mismatch in mismatches {
+                    write!(f, "\n  - {mismatch}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for VerificationError {}
@@ -0,0 +1,249 @@
+use eggmock::egg::{Analysis, EGraph, Id, Language};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::opt_extractor::Extraction;
+
+/// An extractor that pays for each extracted class only once, no matter how many parents or roots
+/// share it. Like [IlpExtractor](crate::ilp_extractor::IlpExtractor), it requires the cost of each
+/// node to be constant, e.g. the cost of compiling the node on its own, which is only a surrogate
+/// for the cost of compiling the extracted network as a whole. In exchange, it only approximates
+/// the optimal selection, but its memory is linear in the size of the e-graph and its work is
+/// bounded by [MAX_REFINEMENT_WORK].
+///
+/// First, each class selects the node with the smallest tree cost, i.e. the summed cost of its
+/// cone with shared classes counted once per use. Then, the selection is refined for all roots at
+/// once: the node of an extracted class is replaced by another one of the same class whenever this
+/// lowers the summed cost of all classes extracted for the roots, e.g. because its children are
+/// already extracted for another root. The extracted classes are tracked with reference counts, so
+/// that a replacement only visits the classes that become extracted or unused by it.
+pub struct DagExtractor<'g, L: Language, A: Analysis<L>> {
+    graph: &'g EGraph<L, A>,
+    nodes: FxHashMap<Id, L>,
+}
+
+/// Maximum number of passes over the extracted classes when refining the selection.
+const MAX_REFINEMENT_PASSES: usize = 8;
+/// Maximum number of classes visited while refining the selection, after which the current
+/// selection is kept.
+const MAX_REFINEMENT_WORK: usize = 1 << 22;
+
+/// The candidate nodes of each class with their costs.
+type Candidates<'g, L> = FxHashMap<Id, Vec<(&'g L, f64)>>;
+
+impl<'g, L: Language, A: Analysis<L>> DagExtractor<'g, L, A> {
+    /// Extracts the given roots, where `cost` returns the cost of a node or [None] if the node must
+    /// not be extracted.
+    pub fn new(
+        graph: &'g EGraph<L, A>,
+        roots: &[Id],
+        mut cost: impl FnMut(&L) -> Option<f64>,
+    ) -> Self {
+        // the candidate nodes of each class with their costs, without those containing self-loops
+        let candidates: Candidates<L> = graph
+            .classes()
+            .map(|class| {
+                let nodes = class
+                    .iter()
+                    .filter(|node| node.all(|child| graph.find(child) != class.id))
+                    .filter_map(|node| Some((node, cost(node)?)))
+                    .collect();
+                (class.id, nodes)
+            })
+            .collect();
+
+        // only strict improvements are taken, so the selection is acyclic
+        let mut selection: FxHashMap<Id, usize> = FxHashMap::default();
+        let mut tree_costs: FxHashMap<Id, f64> = FxHashMap::default();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (class, nodes) in &candidates {
+                for (idx, (node, node_cost)) in nodes.iter().enumerate() {
+                    let Some(children_cost) = node
+                        .children()
+                        .iter()
+                        .map(|child| tree_costs.get(&graph.find(*child)).copied())
+                        .sum::<Option<f64>>()
+                    else {
+                        continue;
+                    };
+                    let total = node_cost + children_cost;
+                    if tree_costs.get(class).is_none_or(|best| total < *best) {
+                        tree_costs.insert(*class, total);
+                        selection.insert(*class, idx);
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        let roots: Vec<Id> = roots.iter().map(|root| graph.find(*root)).collect();
+        // roots without a selected node cannot be extracted, in which case compiling fails
+        if roots.iter().all(|root| selection.contains_key(root)) {
+            let mut refinement = Refinement {
+                graph,
+                candidates: &candidates,
+                selection,
+                refs: FxHashMap::default(),
+                work: 0,
+            };
+            refinement.refine(&roots);
+            selection = refinement.selection;
+        }
+
+        let nodes = selection
+            .into_iter()
+            .map(|(class, idx)| (class, candidates[&class][idx].0.clone()))
+            .collect();
+        Self { graph, nodes }
+    }
+
+    pub fn find_best_node(&self, class: Id) -> Option<&L> {
+        self.nodes.get(&self.graph.find(class))
+    }
+}
+
+/// The state of refining an acyclic selection, where `refs` counts the references of each class by
+/// the roots and the selected nodes of the extracted classes, i.e. those referenced at least once.
+struct Refinement<'c, 'g, L: Language, A: Analysis<L>> {
+    graph: &'g EGraph<L, A>,
+    candidates: &'c Candidates<'g, L>,
+    selection: FxHashMap<Id, usize>,
+    refs: FxHashMap<Id, usize>,
+    /// number of classes visited so far, see [MAX_REFINEMENT_WORK]
+    work: usize,
+}
+
+impl<L: Language, A: Analysis<L>> Refinement<'_, '_, L, A> {
+    fn refine(&mut self, roots: &[Id]) {
+        let mut log = Vec::new();
+        for root in roots {
+            self.reference(*root, &mut log);
+        }
+        for _ in 0..MAX_REFINEMENT_PASSES {
+            let mut improved = false;
+            let extracted: Vec<Id> = self.refs.keys().copied().collect();
+            for class in extracted {
+                // the class may have become unused by a previous replacement
+                if self.refs.get(&class).is_none_or(|refs| *refs == 0) {
+                    continue;
+                }
+                for idx in 0..self.candidates[&class].len() {
+                    if self.work > MAX_REFINEMENT_WORK {
+                        return;
+                    }
+                    improved |= self.try_replace(class, idx);
+                }
+            }
+            if !improved {
+                break;
+            }
+        }
+    }
+
+    /// Selects the given node for the given extracted class if this keeps the selection acyclic
+    /// and lowers the summed cost of the extracted classes. Returns whether it was selected.
+    fn try_replace(&mut self, class: Id, idx: usize) -> bool {
+        let current = self.selection[&class];
+        let (node, node_cost) = self.candidates[&class][idx];
+        if idx == current
+            || !node.all(|child| self.selection.contains_key(&self.graph.find(child)))
+            || self.reaches(node, class)
+        {
+            return false;
+        }
+        let (current_node, current_cost) = self.candidates[&class][current];
+        let mut log = Vec::new();
+        // referencing the new children first keeps classes used by both nodes extracted
+        let mut delta = node_cost - current_cost;
+        for child in node.children() {
+            delta += self.reference(self.graph.find(*child), &mut log);
+        }
+        self.selection.insert(class, idx);
+        for child in current_node.children() {
+            delta -= self.release(self.graph.find(*child), &mut log);
+        }
+        if delta < 0.0 {
+            return true;
+        }
+        self.selection.insert(class, current);
+        for (class, referenced) in log.into_iter().rev() {
+            let refs = self.refs.get_mut(&class).unwrap();
+            if referenced {
+                *refs -= 1;
+            } else {
+                *refs += 1;
+            }
+        }
+        false
+    }
+
+    /// Returns whether the given class is reachable from the children of the given node along the
+    /// selection.
+    fn reaches(&mut self, node: &L, class: Id) -> bool {
+        let mut visited = FxHashSet::default();
+        let mut stack: Vec<Id> = node
+            .children()
+            .iter()
+            .map(|c| self.graph.find(*c))
+            .collect();
+        while let Some(id) = stack.pop() {
+            if id == class {
+                return true;
+            }
+            if !visited.insert(id) {
+                continue;
+            }
+            self.work += 1;
+            let (node, _) = self.candidates[&id][self.selection[&id]];
+            stack.extend(node.children().iter().map(|c| self.graph.find(*c)));
+        }
+        false
+    }
+
+    /// Adds a reference to the given class, which references the children of its node if it
+    /// becomes extracted. Returns the cost of the classes that become extracted.
+    fn reference(&mut self, class: Id, log: &mut Vec<(Id, bool)>) -> f64 {
+        let mut cost = 0.0;
+        let mut stack = vec![class];
+        while let Some(id) = stack.pop() {
+            self.work += 1;
+            log.push((id, true));
+            let refs = self.refs.entry(id).or_default();
+            *refs += 1;
+            if *refs == 1 {
+                let (node, node_cost) = self.candidates[&id][self.selection[&id]];
+                cost += node_cost;
+                stack.extend(node.children().iter().map(|c| self.graph.find(*c)));
+            }
+        }
+        cost
+    }
+
+    /// Removes a reference to the given class, which releases the children of its node if it is no
+    /// longer extracted. Returns the cost of the classes that are no longer extracted.
+    fn release(&mut self, class: Id, log: &mut Vec<(Id, bool)>) -> f64 {
+        let mut cost = 0.0;
+        let mut stack = vec![class];
+        while let Some(id) = stack.pop() {
+            self.work += 1;
+            log.push((id, false));
+            let refs = self.refs.get_mut(&id).unwrap();
+            *refs -= 1;
+            if *refs == 0 {
+                let (node, node_cost) = self.candidates[&id][self.selection[&id]];
+                cost += node_cost;
+                stack.extend(node.children().iter().map(|c| self.graph.find(*c)));
+            }
+        }
+        cost
+    }
+}
+
+impl<L: Language, A: Analysis<L>> Extraction for DagExtractor<'_, L, A> {
+    type Language = L;
+
+    fn find_best_node(&self, class: Id) -> Option<&L> {
+        self.find_best_node(class)
+    }
+}
@@ -0,0 +1,104 @@
+use eggmock::egg::{Analysis, EGraph, Id, Language};
+use microlp::{ComparisonOp, OptimizationDirection, Problem};
+use rustc_hash::FxHashMap;
+
+use crate::opt_extractor::Extraction;
+
+/// An extractor that selects the nodes of minimal total cost by solving an integer linear program.
+/// Unlike [OptExtractor](crate::opt_extractor::OptExtractor), it pays for classes shared by
+/// several parents only once and finds the optimal selection, but the cost of each node has to be
+/// constant, so the selection is only optimal for the summed node costs. Solving takes exponential
+/// time in the worst case and cannot be interrupted, so it is only suited for small e-graphs.
+///
+/// The program has a binary variable for each class and node, telling whether it is extracted. The
+/// classes of the roots are extracted, each extracted class has exactly one extracted node and
+/// each extracted node requires its children to be extracted as well. To rule out cycles, each
+/// class has a level that has to be greater than the levels of the children of its extracted node.
+pub struct IlpExtractor<'g, L: Language, A: Analysis<L>> {
+    graph: &'g EGraph<L, A>,
+    nodes: FxHashMap<Id, L>,
+}
+
+impl<'g, L: Language, A: Analysis<L>> IlpExtractor<'g, L, A> {
+    /// Extracts the given roots, where `cost` returns the cost of a node or [None] if the node must
+    /// not be extracted.
+    pub fn new(
+        graph: &'g EGraph<L, A>,
+        roots: &[Id],
+        mut cost: impl FnMut(&L) -> Option<f64>,
+    ) -> Result<Self, microlp::Error> {
+        let mut problem = Problem::new(OptimizationDirection::Minimize);
+        let max_level = graph.number_of_classes() as f64;
+        let mut extracted = FxHashMap::default();
+        let mut levels = FxHashMap::default();
+        for class in graph.classes() {
+            extracted.insert(class.id, problem.add_binary_var(0.0));
+            levels.insert(class.id, problem.add_var(0.0, (0.0, max_level)));
+        }
+
+        let mut node_vars = Vec::new();
+        for class in graph.classes() {
+            let mut selection = vec![(extracted[&class.id], -1.0)];
+            for node in class.iter() {
+                let mut children: Vec<Id> =
+                    node.children().iter().map(|id| graph.find(*id)).collect();
+                children.sort_unstable();
+                children.dedup();
+                if children.contains(&class.id) {
+                    continue;
+                }
+                let Some(cost) = cost(node) else {
+                    continue;
+                };
+                let var = problem.add_binary_var(cost);
+                selection.push((var, 1.0));
+                for child in children {
+                    problem.add_constraint(
+                        [(var, 1.0), (extracted[&child], -1.0)],
+                        ComparisonOp::Le,
+                        0.0,
+                    );
+                    // level(class) - level(child) >= 1 if the node is extracted
+                    problem.add_constraint(
+                        [
+                            (levels[&class.id], 1.0),
+                            (levels[&child], -1.0),
+                            (var, -(max_level + 1.0)),
+                        ],
+                        ComparisonOp::Ge,
+                        -max_level,
+                    );
+                }
+                node_vars.push((class.id, node, var));
+            }
+            problem.add_constraint(selection, ComparisonOp::Eq, 0.0);
+        }
+        for root in roots {
+            problem.add_constraint(
+                [(extracted[&graph.find(*root)], 1.0)],
+                ComparisonOp::Eq,
+                1.0,
+            );
+        }
+
+        let solution = problem.solve()?;
+        let nodes = node_vars
+            .into_iter()
+            .filter(|(_, _, var)| solution[*var] > 0.5)
+            .map(|(class, node, _)| (class, node.clone()))
+            .collect();
+        Ok(Self { graph, nodes })
+    }
+
+    pub fn find_best_node(&self, class: Id) -> Option<&L> {
+        self.nodes.get(&self.graph.find(class))
+    }
+}
+
+impl<L: Language, A: Analysis<L>> Extraction for IlpExtractor<'_, L, A> {
+    type Language = L;
+
+    fn find_best_node(&self, class: Id) -> Option<&L> {
+        self.find_best_node(class)
+    }
+}
@@ -1,4 +1,6 @@
 #![allow(clippy::upper_case_acronyms)]
 
 mod ambit;
+mod dag_extractor;
+mod ilp_extractor;
 mod opt_extractor;
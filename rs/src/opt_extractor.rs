@@ -1,19 +1,29 @@
-use std::{fmt::Debug, ops::Index};
+use std::{collections::VecDeque, fmt::Debug, ops::Index};
 
 use eggmock::{
     egg::{Analysis, EClass, EGraph, Id, Language},
     EggIdToSignal, Network, NetworkLanguage,
 };
-use rustc_hash::FxHashMap;
+use either::Either;
+use rayon::prelude::*;
+use rustc_hash::{FxHashMap, FxHashSet};
 
 pub trait OptCostFunction<L: Language, A: Analysis<L>> {
-    type Cost: PartialOrd + Debug + Clone;
+    type Cost: Ord + Debug + Clone;
 
     fn cost<C>(&mut self, eclass: &EClass<L, A::Data>, enode: &L, costs: C) -> Option<Self::Cost>
     where
         C: FnMut(Id) -> Self::Cost;
 }
 
+/// A cost function that can be evaluated by several threads at once, see
+/// [OptExtractor::new_parallel].
+pub trait SyncCostFunction<L: Language, A: Analysis<L>>: OptCostFunction<L, A> + Sync {
+    fn sync_cost<C>(&self, eclass: &EClass<L, A::Data>, enode: &L, costs: C) -> Option<Self::Cost>
+    where
+        C: FnMut(Id) -> Self::Cost;
+}
+
 /// An extractor heavily inspired by egg's [Extractor](eggmock::egg::Extractor), which allows
 /// ignoring certain nodes by returning [None] from their cost function.
 pub struct OptExtractor<'g, CF: OptCostFunction<L, A>, L: Language, A: Analysis<L>> {
@@ -33,39 +43,83 @@ impl<'g, CF: OptCostFunction<L, A>, L: Language, A: Analysis<L>> OptExtractor<'g
         extractor
     }
 
+    pub fn cost_function(&self) -> &CF {
+        &self.cost_fn
+    }
+
     pub fn find_best_node(&self, class: Id) -> Option<&L> {
         self.costs
             .get(&self.graph.find(class))
             .map(|(_, node)| node)
     }
 
+    #[cfg(test)]
+    pub fn find_best_cost(&self, class: Id) -> Option<&CF::Cost> {
+        self.costs
+            .get(&self.graph.find(class))
+            .map(|(cost, _)| cost)
+    }
+
+    /// Determines the costs of all classes. Each class is evaluated once initially and then again
+    /// whenever the cost of one of its children improved.
     fn find_costs(&mut self) {
-        let mut changed = true;
-        while changed {
-            changed = false;
-            for class in self.graph.classes() {
-                let new_cost = self.determine_class_costs(class);
-                match (self.costs.get(&class.id), new_cost) {
-                    (None, Some(new)) => {
-                        self.costs.insert(class.id, new);
-                        changed = true;
-                    }
-                    (Some(old), Some(new)) if new.0 < old.0 => {
-                        self.costs.insert(class.id, new);
-                        changed = true;
+        let graph = self.graph;
+        let parents = Self::parents(graph);
+        let mut worklist: VecDeque<Id> = graph.classes().map(|class| class.id).collect();
+        let mut queued: FxHashSet<Id> = worklist.iter().copied().collect();
+        while let Some(id) = worklist.pop_front() {
+            queued.remove(&id);
+            let new_cost = self.determine_class_costs(&graph[id]);
+            if self.update_cost(id, new_cost) {
+                for parent in parents.get(&id).into_iter().flatten() {
+                    if queued.insert(*parent) {
+                        worklist.push_back(*parent);
                     }
-                    _ => (),
                 }
             }
         }
     }
 
+    /// Returns the classes that contain a node with the given class as a child for each class.
+    fn parents(graph: &EGraph<L, A>) -> FxHashMap<Id, Vec<Id>> {
+        let mut parents: FxHashMap<Id, Vec<Id>> = FxHashMap::default();
+        for class in graph.classes() {
+            for child in class.iter().flat_map(|node| node.children()) {
+                parents
+                    .entry(graph.find(*child))
+                    .or_default()
+                    .push(class.id);
+            }
+        }
+        for class_parents in parents.values_mut() {
+            class_parents.sort_unstable();
+            class_parents.dedup();
+        }
+        parents
+    }
+
+    /// Stores the given cost of the given class if it improves on the current one and returns
+    /// whether it did.
+    fn update_cost(&mut self, id: Id, new_cost: Option<(CF::Cost, L)>) -> bool {
+        match (self.costs.get(&id), new_cost) {
+            (None, Some(new)) => {
+                self.costs.insert(id, new);
+                true
+            }
+            (Some(old), Some(new)) if new.0 < old.0 => {
+                self.costs.insert(id, new);
+                true
+            }
+            _ => false,
+        }
+    }
+
     fn determine_class_costs(&mut self, class: &EClass<L, A::Data>) -> Option<(CF::Cost, L)> {
         class
             .iter()
             .map(|node| (self.opt_node_cost(node, class), node))
             .filter_map(|(cost, node)| cost.map(|cost| (cost, node)))
-            .min_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap())
+            .min_by(|(a, _), (b, _)| a.cmp(b))
             .map(|(cost, node)| (cost, node.clone()))
     }
 
@@ -79,15 +133,127 @@ impl<'g, CF: OptCostFunction<L, A>, L: Language, A: Analysis<L>> OptExtractor<'g
     }
 }
 
-pub struct OptExtractionNetwork<E>(pub E, pub Vec<Id>);
+impl<'g, CF, L, A> OptExtractor<'g, CF, L, A>
+where
+    CF: SyncCostFunction<L, A>,
+    CF::Cost: Send + Sync,
+    L: Language + Send + Sync,
+    A: Analysis<L>,
+    EGraph<L, A>: Sync,
+{
+    /// Same as [OptExtractor::new], but evaluates the costs of the nodes on the current rayon
+    /// thread pool. Instead of updating the costs one class at a time, all classes of the frontier
+    /// are evaluated against the costs of the previous frontier, which then consists of the
+    /// parents of the classes whose costs improved. Thus, the result does not depend on the number
+    /// of threads, but may differ from that of [OptExtractor::new].
+    pub fn new_parallel(graph: &'g EGraph<L, A>, cost_fn: CF) -> Self {
+        let mut extractor = Self {
+            graph,
+            cost_fn,
+            costs: FxHashMap::default(),
+        };
+        extractor.find_costs_parallel();
+        extractor
+    }
+
+    fn find_costs_parallel(&mut self) {
+        let graph = self.graph;
+        let parents = Self::parents(graph);
+        let mut frontier: Vec<Id> = graph.classes().map(|class| class.id).collect();
+        while !frontier.is_empty() {
+            let new_costs: Vec<_> = frontier
+                .par_iter()
+                .map(|id| self.determine_class_costs_sync(&graph[*id]))
+                .collect();
+            let mut next = Vec::new();
+            for (id, new_cost) in frontier.into_iter().zip(new_costs) {
+                if self.update_cost(id, new_cost) {
+                    next.extend(parents.get(&id).into_iter().flatten());
+                }
+            }
+            next.sort_unstable();
+            next.dedup();
+            frontier = next;
+        }
+    }
+
+    fn determine_class_costs_sync(&self, class: &EClass<L, A::Data>) -> Option<(CF::Cost, L)> {
+        class
+            .iter()
+            .filter(|node| node.all(|id| self.costs.contains_key(&id)))
+            .filter_map(|node| {
+                let cost = self
+                    .cost_fn
+                    .sync_cost(class, node, |id| self.costs[&self.graph.find(id)].0.clone())?;
+                Some((cost, node))
+            })
+            .min_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(cost, node)| (cost, node.clone()))
+    }
+}
+
+/// The node selected for each class by an extractor.
+pub trait Extraction {
+    type Language: Language;
 
-impl<CF, L, A> Network for OptExtractionNetwork<OptExtractor<'_, CF, L, A>>
+    fn find_best_node(&self, class: Id) -> Option<&Self::Language>;
+}
+
+impl<CF, L, A> Extraction for OptExtractor<'_, CF, L, A>
 where
     CF: OptCostFunction<L, A>,
-    L: NetworkLanguage,
+    L: Language,
     A: Analysis<L>,
 {
-    type Node = L::Node;
+    type Language = L;
+
+    fn find_best_node(&self, class: Id) -> Option<&L> {
+        self.find_best_node(class)
+    }
+}
+
+impl<E1, E2> Extraction for Either<E1, E2>
+where
+    E1: Extraction,
+    E2: Extraction<Language = E1::Language>,
+{
+    type Language = E1::Language;
+
+    fn find_best_node(&self, class: Id) -> Option<&Self::Language> {
+        match self {
+            Either::Left(extraction) => extraction.find_best_node(class),
+            Either::Right(extraction) => extraction.find_best_node(class),
+        }
+    }
+}
+
+pub struct OptExtractionNetwork<E>(pub E, pub Vec<Id>);
+
+impl<E: Extraction> OptExtractionNetwork<E> {
+    /// Returns a class in the cone of the outputs for which no node was extracted, or [None] if the
+    /// network is complete. [Network::node] and [Network::outputs] panic unless it is.
+    pub fn unextractable_class(&self) -> Option<Id> {
+        let mut visited = FxHashSet::default();
+        let mut stack = self.1.clone();
+        while let Some(class) = stack.pop() {
+            if !visited.insert(class) {
+                continue;
+            }
+            let Some(node) = self.0.find_best_node(class) else {
+                return Some(class);
+            };
+            stack.extend(node.children());
+        }
+        None
+    }
+}
+
+impl<E> Network for OptExtractionNetwork<E>
+where
+    E: Extraction,
+    E::Language: NetworkLanguage,
+{
+    type Node = <E::Language as NetworkLanguage>::Node;
 
     fn outputs(&self) -> impl Iterator<Item = eggmock::Signal> {
         self.1.iter().map(|id| IndexWrapper(&self.0).to_signal(*id))
@@ -104,13 +270,8 @@ where
 
 struct IndexWrapper<'e, E>(&'e E);
 
-impl<CF, L, A> Index<Id> for IndexWrapper<'_, OptExtractor<'_, CF, L, A>>
-where
-    CF: OptCostFunction<L, A>,
-    L: NetworkLanguage,
-    A: Analysis<L>,
-{
-    type Output = L;
+impl<E: Extraction> Index<Id> for IndexWrapper<'_, E> {
+    type Output = E::Language;
 
     fn index(&self, index: Id) -> &Self::Output {
         self.0.find_best_node(index).expect("class not extractable")
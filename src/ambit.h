@@ -6,10 +6,44 @@
 #include <mockturtle/networks/mig.hpp>
 
 #include <cstdint>
+#include <optional>
+#include <string>
 #include <utility>
 
 extern "C"
 {
+  // whether compilation succeeded or the kind of error that occurred otherwise
+  enum class ambit_compiler_status : uint8_t
+  {
+    ok,
+    invalid_leaf,
+    row_occupied,
+    not_a_candidate,
+    not_a_maj,
+    no_maj_operation,
+    no_free_dcc,
+    signal_unavailable,
+    // the rules file could not be loaded or a disabled rule does not exist
+    invalid_rules,
+    // the rows of the compiled program could not be allocated in the subarray, e.g. because it has
+    // too few rows to spill to
+    allocation_failed,
+    // no node could be extracted for an e-class the outputs depend on
+    not_extractable,
+  };
+
+  // why the equality saturation stopped
+  enum class ambit_runner_stop_reason : uint8_t
+  {
+    // rewriting is disabled in the settings
+    not_run,
+    saturated,
+    iteration_limit,
+    node_limit,
+    time_limit,
+    other,
+  };
+
   struct ambit_compiler_statistics
   {
     uint64_t egraph_classes;
@@ -17,10 +51,79 @@ extern "C"
     uint64_t egraph_size;
 
     uint64_t instruction_count;
+    // estimated latency (ns) and energy (nJ) of the program
+    double latency;
+    double energy;
 
     uint64_t t_runner;
     uint64_t t_extractor;
     uint64_t t_compiler;
+
+    // true iff verification was enabled and failed
+    bool verification_failed;
+
+    // ok unless the network could not be compiled, in which case all other statistics of the
+    // program are zero
+    ambit_compiler_status status;
+    // null-terminated description of the error if the network could not be compiled
+    char error_message[256];
+
+    // why the equality saturation of the round of the returned program stopped and after how many
+    // iterations
+    ambit_runner_stop_reason runner_stop_reason;
+    uint64_t runner_iterations;
+    // number of e-classes merged by the functional reduction
+    uint64_t functional_merges;
+
+    // the number of rounds and the instruction count of the program compiled in each of them, the
+    // program of the last round that improved on the previous ones is returned
+    uint64_t rounds;
+    uint64_t round_instructions[16];
+
+    // share of the partial networks costed during greedy extraction that were not compiled again
+    // because an identical one was compiled before
+    double compilation_cache_hit_rate;
+
+    // number of physical rows used for spilling if subarray_rows is set
+    uint64_t spill_rows;
+  };
+
+  enum class ambit_architecture_preset : uint8_t
+  {
+    ambit,
+    ambit_inverted_tra,
+    minimal,
+  };
+
+  // the property of the compiled program that is minimized during extraction
+  enum class ambit_extraction_objective : uint8_t
+  {
+    instruction_count,
+    latency,
+    energy,
+    // latency, then energy, then instruction count
+    lexicographic,
+  };
+
+  // how the network to compile is extracted from the e-graph
+  enum class ambit_extraction_method : uint8_t
+  {
+    // selects the cheapest node of each class bottom-up
+    greedy,
+    // solves an integer linear program for the nodes of minimal summed cost of compiling each node on
+    // its own, falling back to greedy extraction for e-graphs of more than 250 nodes
+    ilp,
+    // selects nodes such that classes shared by several parents or outputs are paid for once
+    dag,
+  };
+
+  // the rewrite scheduler of the equality saturation
+  enum class ambit_scheduler : uint8_t
+  {
+    // applies all rewrites in each iteration
+    simple,
+    // temporarily bans rewrites that match too often
+    backoff,
   };
 
   struct ambit_compiler_settings
@@ -29,6 +132,52 @@ extern "C"
     bool verbose;
     bool preoptimize = true;
     bool rewrite = true;
+    ambit_architecture_preset architecture = ambit_architecture_preset::ambit;
+    ambit_extraction_objective objective = ambit_extraction_objective::instruction_count;
+    // check the extracted network and the compiled program against the input network by simulation
+    bool verify = false;
+
+    // limits of the equality saturation
+    uint64_t iter_limit = 30;
+    uint64_t node_limit = 10000;
+    uint64_t time_limit_ms = 5000;
+    ambit_scheduler scheduler = ambit_scheduler::backoff;
+    // initial match limit and ban length of the backoff scheduler
+    uint64_t match_limit = 1000;
+    uint64_t ban_length = 5;
+
+    // file containing rules to rewrite with in addition to the built-in ones, one per line in the
+    // form `name: lhs => rhs` or `name: lhs <=> rhs`
+    char const* rules_file = nullptr;
+    // comma-separated names of rules not to rewrite with, e.g. "associativity,distributivity"
+    char const* disabled_rules = nullptr;
+
+    // merge e-classes proven to be equivalent by simulation between iterations of the equality
+    // saturation
+    bool functional_reduction = false;
+
+    // maximum number of rounds of rewriting, extracting and compiling (at most 16), where each round
+    // starts from the network extracted in the previous one and rounds continue as long as the
+    // instruction count improves
+    uint64_t rounds = 1;
+
+    ambit_extraction_method extraction = ambit_extraction_method::greedy;
+
+    // number of threads evaluating the costs of nodes during greedy extraction, 0 and 1 evaluate
+    // them sequentially
+    uint64_t extraction_threads = 1;
+
+    // number of rows of the subarray to allocate the rows of the compiled program in, 0 skips the
+    // allocation
+    uint64_t subarray_rows = 0;
+    // files to write the DRAMPower command trace of the allocated program (cycle,command,bank) and
+    // the rows activated by it (cycle,bank,subarray,row,rows) to, only written if subarray_rows is set
+    char const* trace_file = nullptr;
+    char const* row_map_file = nullptr;
+
+    // comma-separated names of optional built-in rules to rewrite with, i.e. "relevance" and
+    // "substitution", which grow the e-graph considerably faster than the other rules
+    char const* enabled_rules = nullptr;
   };
 
   struct ambit_compiler_settings_ffi
@@ -36,21 +185,178 @@ extern "C"
     bool print_program;
     bool verbose;
     bool rewrite = true;
+    ambit_architecture_preset architecture = ambit_architecture_preset::ambit;
+    ambit_extraction_objective objective = ambit_extraction_objective::instruction_count;
+    bool verify = false;
+    uint64_t iter_limit;
+    uint64_t node_limit;
+    uint64_t time_limit_ms;
+    ambit_scheduler scheduler;
+    uint64_t match_limit;
+    uint64_t ban_length;
+    char const* rules_file;
+    char const* disabled_rules;
+    bool functional_reduction;
+    uint64_t rounds;
+    ambit_extraction_method extraction;
+    uint64_t extraction_threads;
+    uint64_t subarray_rows;
+    char const* trace_file;
+    char const* row_map_file;
+    char const* enabled_rules;
 
     ambit_compiler_settings_ffi( ambit_compiler_settings s )
-        : print_program( s.print_program ), verbose( s.verbose ), rewrite( s.rewrite ) {}
+        : print_program( s.print_program ),
+          verbose( s.verbose ),
+          rewrite( s.rewrite ),
+          architecture( s.architecture ),
+          objective( s.objective ),
+          verify( s.verify ),
+          iter_limit( s.iter_limit ),
+          node_limit( s.node_limit ),
+          time_limit_ms( s.time_limit_ms ),
+          scheduler( s.scheduler ),
+          match_limit( s.match_limit ),
+          ban_length( s.ban_length ),
+          rules_file( s.rules_file ),
+          disabled_rules( s.disabled_rules ),
+          functional_reduction( s.functional_reduction ),
+          rounds( s.rounds ),
+          extraction( s.extraction ),
+          extraction_threads( s.extraction_threads ),
+          subarray_rows( s.subarray_rows ),
+          trace_file( s.trace_file ),
+          row_map_file( s.row_map_file ),
+          enabled_rules( s.enabled_rules ) {}
+  };
+
+  enum class ambit_opcode : uint8_t
+  {
+    aap,
+    ap,
+  };
+
+  enum class ambit_address_kind : uint8_t
+  {
+    input,
+    output,
+    spill,
+    // index is the value of the constant row, i.e. 0 or 1
+    constant,
+    t,
+    dcc,
+    inverted_dcc,
+    // index is the index of the multi-row activation in the architecture, the activated operands are
+    // operands[first_operand, first_operand + operand_count) of the program
+    multiple,
+  };
+
+  struct ambit_address
+  {
+    ambit_address_kind kind;
+    uint64_t index;
+    uint64_t first_operand;
+    uint64_t operand_count;
+  };
+
+  struct ambit_instruction
+  {
+    ambit_opcode opcode;
+    ambit_address from;
+    // only meaningful for AAPs
+    ambit_address to;
   };
 
+  // a compiled program, which has to be freed with ambit_program_free_ffi
+  struct ambit_program
+  {
+    ambit_instruction* instructions;
+    uint64_t instruction_count;
+    // the operands of all multi-row activations
+    ambit_address* operands;
+    uint64_t operand_count;
+  };
+
+  struct ambit_compiler_output
+  {
+    ambit_compiler_statistics statistics;
+    ambit_program program;
+  };
+
+  void ambit_program_free_ffi( ambit_program program );
+
+  enum class ambit_program_check_status : uint8_t
+  {
+    ok,
+    // the program could not be parsed
+    invalid_program,
+    // the program could not be simulated, e.g. because it reads an uninitialized row
+    simulation_failed,
+    // an output of the program differs from that of the network
+    mismatch,
+  };
+
+  struct ambit_program_check
+  {
+    ambit_program_check_status status;
+    // number of instructions of the program if it could be parsed
+    uint64_t instruction_count;
+    // null-terminated description of the error unless the status is ok
+    char error_message[256];
+  };
+
+  // opaque architecture loaded from a description file
+  struct ambit_architecture;
+
+  ambit_architecture* ambit_architecture_load_ffi( char const* path );
+  void ambit_architecture_free_ffi( ambit_architecture* architecture );
+
   eggmock::mig_receiver<ambit_compiler_statistics> ambit_compile_ffi(
       ambit_compiler_settings_ffi settings );
   eggmock::mig_receiver<ambit_compiler_statistics> ambit_rewrite_ffi(
       ambit_compiler_settings_ffi settings,
       eggmock::mig_receiver<void> receiver );
+  eggmock::mig_receiver<ambit_compiler_statistics> ambit_compile_with_architecture_ffi(
+      ambit_architecture const* architecture,
+      ambit_compiler_settings_ffi settings );
+  eggmock::mig_receiver<ambit_compiler_statistics> ambit_rewrite_with_architecture_ffi(
+      ambit_architecture const* architecture,
+      ambit_compiler_settings_ffi settings,
+      eggmock::mig_receiver<void> receiver );
+  eggmock::mig_receiver<ambit_compiler_output> ambit_compile_program_ffi(
+      ambit_compiler_settings_ffi settings );
+  eggmock::mig_receiver<ambit_compiler_output> ambit_compile_program_with_architecture_ffi(
+      ambit_architecture const* architecture,
+      ambit_compiler_settings_ffi settings );
+  eggmock::mig_receiver<ambit_program_check> ambit_check_program_ffi(
+      ambit_architecture_preset preset,
+      char const* source );
+  eggmock::mig_receiver<ambit_program_check> ambit_check_program_with_architecture_ffi(
+      ambit_architecture const* architecture,
+      char const* source );
+}
+
+inline std::optional<ambit_architecture_preset> get_ambit_architecture_preset( std::string const& name )
+{
+  if ( name == "ambit" )
+  {
+    return ambit_architecture_preset::ambit;
+  }
+  if ( name == "ambit_inverted_tra" )
+  {
+    return ambit_architecture_preset::ambit_inverted_tra;
+  }
+  if ( name == "minimal" )
+  {
+    return ambit_architecture_preset::minimal;
+  }
+  return {};
 }
 
 inline std::pair<mockturtle::mig_network, ambit_compiler_statistics> ambit_rewrite(
     ambit_compiler_settings settings,
-    mockturtle::mig_network& ntk )
+    mockturtle::mig_network& ntk,
+    ambit_architecture const* architecture = nullptr )
 {
   if ( settings.preoptimize )
   {
@@ -58,19 +364,53 @@ inline std::pair<mockturtle::mig_network, ambit_compiler_statistics> ambit_rewri
   }
   mockturtle::mig_network out;
   const auto stat = eggmock::send_mig(
-      ntk, ambit_rewrite_ffi( settings, eggmock::receive_mig( out ) ) );
+      ntk, architecture
+               ? ambit_rewrite_with_architecture_ffi( architecture, settings, eggmock::receive_mig( out ) )
+               : ambit_rewrite_ffi( settings, eggmock::receive_mig( out ) ) );
   return { out, stat };
 }
 
 inline ambit_compiler_statistics ambit_compile(
     ambit_compiler_settings settings,
-    mockturtle::mig_network& ntk )
+    mockturtle::mig_network& ntk,
+    ambit_architecture const* architecture = nullptr )
 {
   if ( settings.preoptimize )
   {
     preoptimize_mig( ntk );
   }
   mockturtle::mig_network out;
-  const auto stat = eggmock::send_mig( ntk, ambit_compile_ffi( settings ) );
+  const auto stat = eggmock::send_mig(
+      ntk, architecture ? ambit_compile_with_architecture_ffi( architecture, settings )
+                        : ambit_compile_ffi( settings ) );
   return stat;
 }
+
+// same as ambit_compile, but also returns the compiled program, which has to be freed with
+// ambit_program_free_ffi
+inline ambit_compiler_output ambit_compile_program(
+    ambit_compiler_settings settings,
+    mockturtle::mig_network& ntk,
+    ambit_architecture const* architecture = nullptr )
+{
+  if ( settings.preoptimize )
+  {
+    preoptimize_mig( ntk );
+  }
+  return eggmock::send_mig(
+      ntk, architecture ? ambit_compile_program_with_architecture_ffi( architecture, settings )
+                        : ambit_compile_program_ffi( settings ) );
+}
+
+// checks the program given in its textual representation, i.e. as printed with print_program,
+// against the network by simulation
+inline ambit_program_check ambit_check_program(
+    char const* source,
+    mockturtle::mig_network& ntk,
+    ambit_architecture_preset preset = ambit_architecture_preset::ambit,
+    ambit_architecture const* architecture = nullptr )
+{
+  return eggmock::send_mig(
+      ntk, architecture ? ambit_check_program_with_architecture_ffi( architecture, source )
+                        : ambit_check_program_ffi( preset, source ) );
+}
@@ -10,15 +10,44 @@ using namespace mockturtle;
 using namespace eggmock;
 using namespace std::chrono;
 
-// usage: exec [network]
+// usage: exec [network] [architecture preset or file]?
 int main( int const argc, char** argv )
 {
-  if ( argc != 2 )
+  if ( argc != 2 && argc != 3 )
   {
-    std::cerr << "usage: " << argv[0] << std::endl;
+    std::cerr << "usage: " << argv[0] << " [network] [architecture preset or file]?" << std::endl;
     return 1;
   }
 
+  auto settings = ambit_compiler_settings{
+      .print_program = false,
+      .verbose = false,
+      .verify = true,
+  };
+  ambit_architecture* architecture = nullptr;
+  if ( argc == 3 )
+  {
+    std::string const key = argv[2];
+    // Read from file
+    if ( key.find( '.' ) != std::string::npos )
+    {
+      architecture = ambit_architecture_load_ffi( key.c_str() );
+      if ( !architecture )
+      {
+        return 1;
+      }
+    }
+    else if ( auto const preset = get_ambit_architecture_preset( key ) )
+    {
+      settings.architecture = *preset;
+    }
+    else
+    {
+      std::cerr << "invalid architecture '" << key << "'" << std::endl;
+      return 1;
+    }
+  }
+
   std::optional<mig_network> mig = get_ntk<mig_network>( argv[1] );
   if ( !mig )
   {
@@ -31,18 +60,29 @@ int main( int const argc, char** argv )
   preoptimize_mig( *mig );
   auto const t_opt = duration_cast<milliseconds>( system_clock::now() - opt_begin ).count();
 
-  auto constexpr settings = ambit_compiler_settings{
-      .print_program = false,
-      .verbose = false,
-  };
-
   const auto [egraph_classes, egraph_nodes, egraph_size,
-              instruction_count,
-              t_runner, t_extractor, t_compiler] = ambit_compile( settings, *mig );
+              instruction_count, latency, energy,
+              t_runner, t_extractor, t_compiler,
+              verification_failed, status, error_message,
+              runner_stop_reason, runner_iterations, functional_merges,
+              rounds, round_instructions, compilation_cache_hit_rate,
+              spill_rows] = ambit_compile( settings, *mig, architecture );
+  ambit_architecture_free_ffi( architecture );
+  if ( status != ambit_compiler_status::ok )
+  {
+    std::cerr << "could not compile the network: " << error_message << std::endl;
+    return 1;
+  }
+  if ( verification_failed )
+  {
+    std::cerr << "compiled program does not match the input network" << std::endl;
+    return 1;
+  }
 
   std::cout << t_opt << "\t" << t_runner << "\t" << t_extractor << "\t" << t_compiler << "\t"
             << pre_opt_size << "\t" << mig->size() << "\t" << mig->num_cis() << "\t" << mig->num_cos() << "\t"
             << instruction_count << "\t"
-            << egraph_classes << "\t" << egraph_nodes << "\t" << egraph_size;
+            << egraph_classes << "\t" << egraph_nodes << "\t" << egraph_size << "\t"
+            << latency << "\t" << energy;
   return 0;
 }
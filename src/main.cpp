@@ -28,7 +28,14 @@ int main()
   };
 
   auto [out, result] = ambit_rewrite( settings, in );
+  if ( result.status != ambit_compiler_status::ok )
+  {
+    std::cerr << "could not compile the network: " << result.error_message << std::endl;
+    return 1;
+  }
   std::cout << "IC:" << result.instruction_count << std::endl;
+  std::cout << "latency:" << result.latency << "ns" << std::endl;
+  std::cout << "energy:" << result.energy << "nJ" << std::endl;
   std::cout << "t1:" << result.t_runner << std::endl;
   std::cout << "t2:" << result.t_extractor << std::endl;
   std::cout << "t3:" << result.t_compiler << std::endl;